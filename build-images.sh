@@ -1,4 +1,7 @@
 #/bin/sh
 
-docker build -t cargo-sandbox-build - < cargo-sandbox-build.Dockerfile && \
-docker build -t cargo-sandbox-publish - < cargo-sandbox-publish.Dockerfile
+# cargo-sandbox builds missing images itself, this builds them ahead of time
+VERSION=$(sed -n 's/^version = "\(.*\)"$/\1/p' Cargo.toml | head -n 1)
+
+docker build -t cargo-sandbox-build:$VERSION - < cargo-sandbox-build.Dockerfile && \
+docker build -t cargo-sandbox-publish:$VERSION - < cargo-sandbox-publish.Dockerfile
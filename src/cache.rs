@@ -0,0 +1,137 @@
+use maplit::hashmap;
+
+use crate::dockerapi::client::Client;
+use crate::dockerapi::create_volume_args::CreateVolumeArgs;
+use crate::dockerapi::list_volumes::ListVolumesArgs;
+use crate::dockerapi::mount::Mount;
+use crate::project::{Project, PROJECT_ID_LABEL, PROJECT_NAME_LABEL};
+
+/// `CARGO_HOME` in the sandbox images, as set by the upstream `rust` images.
+pub const DOCKER_CARGO_HOME: &str = "/usr/local/cargo";
+
+const CACHE_LABEL: &str = "cargo-sandbox.cache";
+
+/// The parts of `CARGO_HOME` that hold downloaded dependencies.
+const CACHED_DIRECTORIES: &[&str] = &["registry", "git"];
+
+/// Cargo's dependency cache for one project, stored in docker volumes that are mounted
+/// over the cache directories in `CARGO_HOME`.
+///
+/// Volumes are labelled with the project they belong to and are never shared with another
+/// project, so one project's build scripts can't poison another project's dependencies.
+pub struct DependencyCache {
+    volumes: Vec<(String, String)>,
+    persist: bool,
+}
+
+impl DependencyCache {
+    /// Creates the project's cache volumes, or reuses them if they already exist. Unless
+    /// `persist` is set, the volumes are unique to this invocation.
+    pub async fn create(client: &Client, project: &Project, persist: bool) -> eyre::Result<DependencyCache> {
+        let volume_prefix = if persist {
+            project.volume_prefix()
+        } else {
+            format!("{}-{}", project.volume_prefix(), std::process::id())
+        };
+
+        let mut volumes = Vec::with_capacity(CACHED_DIRECTORIES.len());
+        for directory in CACHED_DIRECTORIES {
+            let volume = client
+                .create_volume(CreateVolumeArgs {
+                    name: format!("{volume_prefix}-{directory}"),
+                    labels: hashmap! {
+                        "cargo-sandbox.version".into() => env!("CARGO_PKG_VERSION").to_string(),
+                        PROJECT_ID_LABEL.into() => project.id.clone(),
+                        PROJECT_NAME_LABEL.into() => project.name.clone(),
+                        CACHE_LABEL.into() => directory.to_string(),
+                    },
+                    ..Default::default()
+                })
+                .await?;
+            volumes.push((volume.name, format!("{DOCKER_CARGO_HOME}/{directory}")));
+        }
+
+        Ok(DependencyCache { volumes, persist })
+    }
+
+    pub fn mounts(&self) -> Vec<Mount> {
+        self.volumes
+            .iter()
+            .map(|(name, target)| Mount::volume(name, target))
+            .collect()
+    }
+
+    /// Removes the volumes, unless they are meant to persist.
+    pub async fn release(self, client: &Client) {
+        if self.persist {
+            return;
+        }
+        for (name, _) in &self.volumes {
+            if let Err(e) = client.remove_volume(name, true).await {
+                eprintln!("Failed to remove dependency volume {name}: {e}");
+            }
+        }
+    }
+}
+
+/// The volume that holds the project's `target` directory when the project is mounted
+/// read-only. It is created if it does not exist yet.
+pub async fn target_volume(client: &Client, project: &Project) -> eyre::Result<String> {
+    let volume = client
+        .create_volume(CreateVolumeArgs {
+            name: format!("{}-target", project.volume_prefix()),
+            labels: hashmap! {
+                "cargo-sandbox.version".into() => env!("CARGO_PKG_VERSION").to_string(),
+                PROJECT_ID_LABEL.into() => project.id.clone(),
+                PROJECT_NAME_LABEL.into() => project.name.clone(),
+                CACHE_LABEL.into() => "target".into(),
+            },
+            ..Default::default()
+        })
+        .await?;
+    Ok(volume.name)
+}
+
+/// A volume for a single invocation, such as the target directory of `cargo package`.
+/// It is labelled like a cache so that `prune` removes it if it is ever left behind.
+pub async fn scratch_volume(client: &Client, project: &Project, purpose: &str) -> eyre::Result<String> {
+    let volume = client
+        .create_volume(CreateVolumeArgs {
+            name: format!("{}-{purpose}-{}", project.volume_prefix(), std::process::id()),
+            labels: hashmap! {
+                "cargo-sandbox.version".into() => env!("CARGO_PKG_VERSION").to_string(),
+                PROJECT_ID_LABEL.into() => project.id.clone(),
+                PROJECT_NAME_LABEL.into() => project.name.clone(),
+                CACHE_LABEL.into() => purpose.into(),
+            },
+            ..Default::default()
+        })
+        .await?;
+    Ok(volume.name)
+}
+
+/// Removes the dependency cache and target volumes of `project`, or of every project
+/// along with the toolchain volumes that projects share.
+pub async fn prune(client: &Client, project: Option<&Project>) -> eyre::Result<()> {
+    let label_filter = match project {
+        Some(project) => vec![format!("{PROJECT_ID_LABEL}={}", project.id), CACHE_LABEL.to_string()],
+        None => vec![CACHE_LABEL.to_string()],
+    };
+    let volumes = client
+        .list_volumes(ListVolumesArgs {
+            filters: Some(
+                serde_json::json!({
+                    "label": label_filter,
+                })
+                .to_string(),
+            ),
+        })
+        .await?;
+
+    for volume in volumes {
+        client.remove_volume(&volume.name, false).await?;
+        println!("Removed {}", volume.name);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,782 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+
+use serde::Deserialize;
+
+use crate::container_type::ContainerType;
+
+/// The name of the project configuration file, which lives next to `Cargo.toml`.
+pub const PROJECT_CONFIG_FILE: &str = "cargo-sandbox.toml";
+
+/// The user configuration file, relative to `$XDG_CONFIG_HOME`.
+pub const USER_CONFIG_FILE: &str = "cargo-sandbox/config.toml";
+
+/// Where values given on the command line came from, in `show`.
+const COMMAND_LINE: &str = "command line";
+
+/// The process limit if none is configured, low enough to stop a fork bomb but far above
+/// what a parallel build needs.
+const DEFAULT_PIDS_LIMIT: i64 = 4096;
+
+/// Sandbox policy for a project.
+///
+/// Defaults for every project are read from `$XDG_CONFIG_HOME/cargo-sandbox/config.toml`.
+/// Project configuration is layered over that, and is read from `cargo-sandbox.toml` in the
+/// project root or, alternatively, from the `[package.metadata.sandbox]` table in `Cargo.toml`.
+/// Every key is optional, unknown keys are rejected so that a typo can't silently weaken
+/// the policy. Project values replace user values, lists are appended to the user's lists.
+///
+/// ```toml
+/// docker-socket = "/var/run/docker.sock"
+///
+/// [images]
+/// build = "registry.example.com/cargo-sandbox-build:0.1.0"
+/// publish = "registry.example.com/cargo-sandbox-publish:0.1.0"
+///
+/// [project]
+/// read-only = false
+///
+/// [network]
+/// allow = false
+///
+/// [cache]
+/// persist = true
+///
+/// [[mounts]]
+/// source = "../fixtures"
+/// target = "/home/cargo-sandbox-user/fixtures"
+/// read-only = true
+///
+/// [env]
+/// pass = ["RUST_LOG"]
+///
+/// [limits]
+/// memory = "4g"
+/// memory-swap = "4g"
+/// cpus = 2.0
+/// pids = 1024
+/// storage = "20g"
+/// ulimits = { nofile = 4096 }
+/// timeout = "30m"
+///
+/// [security]
+/// cap-add = ["NET_RAW"]
+/// read-only-rootfs = true
+/// no-new-privileges = true
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Config {
+    /// Path to the docker daemon's unix socket. `--docker-host`, `DOCKER_HOST` and the
+    /// active docker context take precedence over this.
+    pub docker_socket: Option<PathBuf>,
+    #[serde(default)]
+    pub images: ImagesConfig,
+    #[serde(default)]
+    pub project: ProjectConfig,
+    #[serde(default)]
+    pub network: NetworkConfig,
+    #[serde(default)]
+    pub cache: CacheConfig,
+    /// Host directories to mount into the Build container, in addition to the project.
+    #[serde(default)]
+    pub mounts: Vec<MountConfig>,
+    #[serde(default)]
+    pub env: EnvConfig,
+    #[serde(default)]
+    pub limits: LimitsConfig,
+    #[serde(default)]
+    pub security: SecurityConfig,
+    /// The file that each value was read from, keyed by the value's path in `show`.
+    #[serde(skip)]
+    pub sources: BTreeMap<String, PathBuf>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct ImagesConfig {
+    /// The image used for the Build container.
+    pub build: Option<String>,
+    /// The image used for the Publish container.
+    pub publish: Option<String>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct ProjectConfig {
+    /// Mount the project read-only into the Build container and keep `target` in a volume,
+    /// so that build scripts can't modify the source tree.
+    pub read_only: Option<bool>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct NetworkConfig {
+    /// Give commands network access after dependencies have been fetched, as `--allow-network` does.
+    pub allow: Option<bool>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct CacheConfig {
+    /// Keep the project's dependency cache between invocations, rather than downloading
+    /// every dependency again. Defaults to `true`.
+    pub persist: Option<bool>,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct MountConfig {
+    /// Host path. Relative paths are relative to the directory containing the configuration file.
+    pub source: PathBuf,
+    /// Absolute path in the container.
+    pub target: String,
+    /// Extra mounts are read-only unless this is set to `false`.
+    #[serde(default = "default_read_only")]
+    pub read_only: bool,
+}
+
+fn default_read_only() -> bool {
+    true
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct EnvConfig {
+    /// Names of host environment variables to pass into the Build container, or `*` glob patterns
+    /// such as `CARGO_*`. Credentials such as `CARGO_REGISTRY_TOKEN` are never passed.
+    #[serde(default)]
+    pub pass: Vec<String>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct LimitsConfig {
+    /// Memory limit, e.g. `"4g"`.
+    pub memory: Option<ByteSize>,
+    /// Memory plus swap. Defaults to `memory`, so that a build that runs out of memory is
+    /// killed rather than swapping the host to a halt.
+    pub memory_swap: Option<ByteSize>,
+    /// Number of CPUs, which may be fractional.
+    pub cpus: Option<Cpus>,
+    /// Maximum number of processes and threads. Defaults to 4096.
+    pub pids: Option<PidsLimit>,
+    /// Size limit of the container's writable layer. Only some storage drivers support this,
+    /// e.g. overlay2 on xfs mounted with `pquota`.
+    pub storage: Option<ByteSize>,
+    /// Resource limits by `ulimit` name, e.g. `nofile`, applied as both soft and hard limit.
+    #[serde(default)]
+    pub ulimits: BTreeMap<String, i64>,
+    /// Wall-clock time that a single sandboxed command may take before its container is
+    /// killed, e.g. `"30m"`.
+    pub timeout: Option<Timeout>,
+}
+
+/// Relaxations of the hardening that every Build container gets: no capabilities, a
+/// read-only root filesystem and `no-new-privileges`. The Publish container is never relaxed.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct SecurityConfig {
+    /// Capabilities to give back, e.g. `NET_RAW` for `ping`. `ALL` is rejected.
+    #[serde(default)]
+    pub cap_add: Vec<String>,
+    /// Defaults to `true`. Only `/tmp`, `~/.cache`, the project and the volumes are writable.
+    pub read_only_rootfs: Option<bool>,
+    /// Defaults to `true`.
+    pub no_new_privileges: Option<bool>,
+}
+
+impl Config {
+    /// Loads the user configuration and the configuration for the project rooted at
+    /// `project_root`, with the project's layered over the user's.
+    pub fn load(project_root: &Path) -> eyre::Result<Config> {
+        let mut config = Config::default();
+
+        if let Some(user_config_path) = user_config_path() {
+            if let Some(contents) = read_optional(&user_config_path)? {
+                let user_config = parse::<Config>(&user_config_path, &contents)?;
+                config.merge(user_config, &user_config_path);
+            }
+        }
+
+        if let Some((project_config_path, project_config)) = Self::load_project(project_root)? {
+            config.merge(project_config, &project_config_path);
+        }
+
+        for cap in &config.security.cap_add {
+            if cap.eq_ignore_ascii_case("ALL") || cap.eq_ignore_ascii_case("CAP_ALL") {
+                eyre::bail!(
+                    "security.cap-add = {cap:?} would undo dropping capabilities, list the ones that are needed instead",
+                );
+            }
+        }
+
+        Ok(config)
+    }
+
+    /// Loads only the project's configuration, along with the file it came from.
+    fn load_project(project_root: &Path) -> eyre::Result<Option<(PathBuf, Config)>> {
+        let config_path = project_root.join(PROJECT_CONFIG_FILE);
+        let from_config_file = match read_optional(&config_path)? {
+            Some(contents) => Some(parse::<Config>(&config_path, &contents)?),
+            None => None,
+        };
+
+        let manifest_path = project_root.join("Cargo.toml");
+        let from_manifest = match read_optional(&manifest_path)? {
+            Some(contents) => parse::<Manifest>(&manifest_path, &contents)?
+                .package
+                .and_then(|package| package.metadata)
+                .and_then(|metadata| metadata.sandbox),
+            None => None,
+        };
+
+        match (from_config_file, from_manifest) {
+            (Some(_), Some(_)) => eyre::bail!(
+                "Both {} and [package.metadata.sandbox] in {} configure cargo-sandbox, only one of them may be used",
+                config_path.display(),
+                manifest_path.display(),
+            ),
+            (Some(config), None) => Ok(Some((config_path, config))),
+            (None, Some(config)) => Ok(Some((manifest_path, config))),
+            (None, None) => Ok(None),
+        }
+    }
+
+    /// Layers `other`, read from `path`, over this configuration.
+    fn merge(&mut self, other: Config, path: &Path) {
+        self.merge_limits(other.limits, path);
+        let sources = &mut self.sources;
+        merge_value(&mut self.docker_socket, other.docker_socket, "docker-socket", path, sources);
+        merge_value(&mut self.images.build, other.images.build, "images.build", path, sources);
+        merge_value(&mut self.images.publish, other.images.publish, "images.publish", path, sources);
+        merge_value(&mut self.project.read_only, other.project.read_only, "project.read-only", path, sources);
+        merge_value(&mut self.network.allow, other.network.allow, "network.allow", path, sources);
+        merge_value(&mut self.cache.persist, other.cache.persist, "cache.persist", path, sources);
+        let security = other.security;
+        merge_value(&mut self.security.read_only_rootfs, security.read_only_rootfs, "security.read-only-rootfs", path, sources);
+        merge_value(&mut self.security.no_new_privileges, security.no_new_privileges, "security.no-new-privileges", path, sources);
+
+        let directory = path.parent().unwrap_or_else(|| Path::new(""));
+        for mut mount in other.mounts {
+            mount.source = directory.join(&mount.source);
+            sources.insert(format!("mounts.{}", mount.target), path.to_path_buf());
+            self.mounts.push(mount);
+        }
+        for key in other.env.pass {
+            sources.insert(format!("env.pass.{key}"), path.to_path_buf());
+            self.env.pass.push(key);
+        }
+        for cap in security.cap_add {
+            sources.insert(format!("security.cap-add.{cap}"), path.to_path_buf());
+            self.security.cap_add.push(cap);
+        }
+    }
+
+    fn merge_limits(&mut self, other: LimitsConfig, path: &Path) {
+        let sources = &mut self.sources;
+        merge_value(&mut self.limits.memory, other.memory, "limits.memory", path, sources);
+        merge_value(&mut self.limits.memory_swap, other.memory_swap, "limits.memory-swap", path, sources);
+        merge_value(&mut self.limits.cpus, other.cpus, "limits.cpus", path, sources);
+        merge_value(&mut self.limits.pids, other.pids, "limits.pids", path, sources);
+        merge_value(&mut self.limits.storage, other.storage, "limits.storage", path, sources);
+        merge_value(&mut self.limits.timeout, other.timeout, "limits.timeout", path, sources);
+        for (name, limit) in other.ulimits {
+            sources.insert(format!("limits.ulimits.{name}"), path.to_path_buf());
+            self.limits.ulimits.insert(name, limit);
+        }
+    }
+
+    /// Layers limits given on the command line over the configured ones.
+    pub fn override_limits(&mut self, limits: LimitsConfig) {
+        self.merge_limits(limits, Path::new(COMMAND_LINE));
+    }
+
+    /// Renders the effective configuration, noting which file each value came from.
+    pub fn show(&self) -> String {
+        let mut out = String::new();
+        let mut line = |key: &str, value: String| {
+            let source = self
+                .sources
+                .get(key)
+                .map_or_else(|| "default".to_string(), |path| path.display().to_string());
+            let _ = writeln!(out, "{value:<60} # {source}");
+        };
+
+        match &self.docker_socket {
+            Some(socket) => line("docker-socket", format!("docker-socket = {socket:?}")),
+            None => line("docker-socket", "docker-socket = \"auto\"".to_string()),
+        }
+        line("images.build", format!("images.build = {:?}", self.image(ContainerType::Build)));
+        line("images.publish", format!("images.publish = {:?}", self.image(ContainerType::Publish)));
+        line("project.read-only", format!("project.read-only = {}", self.read_only_project()));
+        line("network.allow", format!("network.allow = {}", self.allow_network()));
+        line("cache.persist", format!("cache.persist = {}", self.persist_cache()));
+        for mount in &self.mounts {
+            line(
+                &format!("mounts.{}", mount.target),
+                format!(
+                    "mounts = {{ source = {:?}, target = {:?}, read-only = {} }}",
+                    mount.source, mount.target, mount.read_only
+                ),
+            );
+        }
+        for key in &self.env.pass {
+            line(&format!("env.pass.{key}"), format!("env.pass = {key:?}"));
+        }
+        let unlimited = || "\"unlimited\"".to_string();
+        line(
+            "limits.memory",
+            format!("limits.memory = {}", self.limits.memory.map_or_else(unlimited, |memory| memory.0.to_string())),
+        );
+        line(
+            "limits.memory-swap",
+            format!("limits.memory-swap = {}", self.memory_swap().map_or_else(unlimited, |swap| swap.to_string())),
+        );
+        line(
+            "limits.cpus",
+            format!("limits.cpus = {}", self.limits.cpus.map_or_else(unlimited, |cpus| cpus.0.to_string())),
+        );
+        line("limits.pids", format!("limits.pids = {}", self.pids_limit()));
+        line(
+            "limits.storage",
+            format!("limits.storage = {}", self.limits.storage.map_or_else(unlimited, |storage| storage.0.to_string())),
+        );
+        for (name, limit) in &self.limits.ulimits {
+            line(&format!("limits.ulimits.{name}"), format!("limits.ulimits.{name} = {limit}"));
+        }
+        line(
+            "limits.timeout",
+            format!("limits.timeout = {}", self.limits.timeout.map_or_else(unlimited, |timeout| format!("\"{timeout}\""))),
+        );
+        for cap in &self.security.cap_add {
+            line(&format!("security.cap-add.{cap}"), format!("security.cap-add = {cap:?}"));
+        }
+        line("security.read-only-rootfs", format!("security.read-only-rootfs = {}", self.read_only_rootfs()));
+        line("security.no-new-privileges", format!("security.no-new-privileges = {}", self.no_new_privileges()));
+
+        out
+    }
+
+    pub fn image(&self, container_type: ContainerType) -> String {
+        let image = match container_type {
+            ContainerType::Build => &self.images.build,
+            ContainerType::Publish => &self.images.publish,
+        };
+        image.clone().unwrap_or_else(|| container_type.default_image())
+    }
+
+    pub fn read_only_project(&self) -> bool {
+        self.project.read_only.unwrap_or(false)
+    }
+
+    pub fn allow_network(&self) -> bool {
+        self.network.allow.unwrap_or(false)
+    }
+
+    pub fn persist_cache(&self) -> bool {
+        self.cache.persist.unwrap_or(true)
+    }
+
+    /// Memory plus swap in bytes, if memory is limited at all.
+    pub fn memory_swap(&self) -> Option<i64> {
+        self.limits.memory_swap.or(self.limits.memory).map(|size| size.0)
+    }
+
+    pub fn pids_limit(&self) -> i64 {
+        self.limits.pids.map_or(DEFAULT_PIDS_LIMIT, |pids| pids.0)
+    }
+
+    pub fn read_only_rootfs(&self) -> bool {
+        self.security.read_only_rootfs.unwrap_or(true)
+    }
+
+    pub fn no_new_privileges(&self) -> bool {
+        self.security.no_new_privileges.unwrap_or(true)
+    }
+
+    /// How the Build container is less hardened than the default, for humans.
+    pub fn security_relaxations(&self) -> Vec<String> {
+        let mut relaxations: Vec<String> = self
+            .security
+            .cap_add
+            .iter()
+            .map(|cap| format!("capability {cap} added"))
+            .collect();
+        if !self.read_only_rootfs() {
+            relaxations.push("root filesystem writable".to_string());
+        }
+        if !self.no_new_privileges() {
+            relaxations.push("no-new-privileges disabled".to_string());
+        }
+        relaxations
+    }
+}
+
+fn merge_value<T>(
+    target: &mut Option<T>,
+    value: Option<T>,
+    key: &str,
+    path: &Path,
+    sources: &mut BTreeMap<String, PathBuf>,
+) {
+    if let Some(value) = value {
+        *target = Some(value);
+        sources.insert(key.to_string(), path.to_path_buf());
+    }
+}
+
+/// `$XDG_CONFIG_HOME/cargo-sandbox/config.toml`, falling back to `~/.config` as the
+/// XDG base directory specification requires.
+fn user_config_path() -> Option<PathBuf> {
+    let config_home = std::env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(config_home.join(USER_CONFIG_FILE))
+}
+
+/// Every file in the project that sandbox policy is read from, whether it exists or not: the
+/// project configuration, the root manifest, and cargo's configuration between `current_dir`
+/// and the root, which decides which path dependencies are mounted. Sandboxed code must never
+/// be able to write these, or the next run would follow its policy instead.
+pub fn policy_files(project_root: &Path, current_dir: &Path) -> Vec<PathBuf> {
+    let mut files = vec![project_root.join(PROJECT_CONFIG_FILE), project_root.join("Cargo.toml")];
+    for dir in current_dir.ancestors().take_while(|dir| dir.starts_with(project_root)) {
+        files.push(dir.join(".cargo").join("config.toml"));
+        files.push(dir.join(".cargo").join("config"));
+    }
+    files
+}
+
+pub(crate) fn read_optional(path: &Path) -> eyre::Result<Option<String>> {
+    match std::fs::read_to_string(path) {
+        Ok(contents) => Ok(Some(contents)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(eyre::eyre!("Failed to read {}: {e}", path.display())),
+    }
+}
+
+/// toml's errors include the line and column of the offending key, so we only add the file.
+fn parse<T: serde::de::DeserializeOwned>(path: &Path, contents: &str) -> eyre::Result<T> {
+    toml::from_str(contents)
+        .map_err(|e| eyre::eyre!("Invalid cargo-sandbox configuration in {}\n{e}", path.display()))
+}
+
+/// Only the parts of `Cargo.toml` that can hold our configuration. Deserializing these
+/// directly, rather than going through a `toml::Value`, keeps error locations intact.
+#[derive(Deserialize)]
+struct Manifest {
+    package: Option<ManifestPackage>,
+}
+
+#[derive(Deserialize)]
+struct ManifestPackage {
+    metadata: Option<ManifestMetadata>,
+}
+
+#[derive(Deserialize)]
+struct ManifestMetadata {
+    sandbox: Option<Config>,
+}
+
+/// A number of bytes, written either as an integer or as a string with a
+/// `k`, `m` or `g` suffix, e.g. `"512m"`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ByteSize(pub i64);
+
+impl FromStr for ByteSize {
+    type Err = eyre::Report;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let lower = s.trim().to_ascii_lowercase();
+        let lower = lower.trim_end_matches('b');
+        let (number, multiplier) = match lower.char_indices().last() {
+            Some((index, 'k')) => (&lower[..index], 1 << 10),
+            Some((index, 'm')) => (&lower[..index], 1 << 20),
+            Some((index, 'g')) => (&lower[..index], 1 << 30),
+            _ => (lower, 1),
+        };
+        let number: i64 = number
+            .trim()
+            .parse()
+            .map_err(|_| eyre::eyre!("Invalid size `{s}`, expected e.g. `512m` or `4g`"))?;
+        match number.checked_mul(multiplier) {
+            Some(bytes) if bytes <= 0 => eyre::bail!("Invalid size `{s}`, it must be larger than zero"),
+            Some(bytes) => Ok(ByteSize(bytes)),
+            None => eyre::bail!("Size `{s}` is too large"),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for ByteSize {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Raw {
+            Bytes(i64),
+            Text(String),
+        }
+
+        match Raw::deserialize(deserializer)? {
+            Raw::Bytes(bytes) => bytes.to_string().parse().map_err(serde::de::Error::custom),
+            Raw::Text(text) => text.parse().map_err(serde::de::Error::custom),
+        }
+    }
+}
+
+/// A wall-clock duration, written either as a number of seconds or as a string with an
+/// `s`, `m` or `h` suffix, e.g. `"90s"` or `"30m"`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Timeout(pub Duration);
+
+impl FromStr for Timeout {
+    type Err = eyre::Report;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let trimmed = s.trim();
+        let (number, multiplier) = match trimmed.char_indices().last() {
+            Some((index, 's')) => (&trimmed[..index], 1),
+            Some((index, 'm')) => (&trimmed[..index], 60),
+            Some((index, 'h')) => (&trimmed[..index], 60 * 60),
+            _ => (trimmed, 1),
+        };
+        let number: u64 = number
+            .trim()
+            .parse()
+            .map_err(|_| eyre::eyre!("Invalid duration `{s}`, expected e.g. `90s` or `30m`"))?;
+        match number.checked_mul(multiplier) {
+            Some(0) => eyre::bail!("A timeout of `{s}` would kill every command immediately"),
+            Some(seconds) => Ok(Timeout(Duration::from_secs(seconds))),
+            None => eyre::bail!("Duration `{s}` is too long"),
+        }
+    }
+}
+
+impl std::fmt::Display for Timeout {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let seconds = self.0.as_secs();
+        if seconds.is_multiple_of(60 * 60) {
+            write!(f, "{}h", seconds / (60 * 60))
+        } else if seconds.is_multiple_of(60) {
+            write!(f, "{}m", seconds / 60)
+        } else {
+            write!(f, "{seconds}s")
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Timeout {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Raw {
+            Seconds(u64),
+            Text(String),
+        }
+
+        match Raw::deserialize(deserializer)? {
+            Raw::Seconds(seconds) => seconds.to_string().parse().map_err(serde::de::Error::custom),
+            Raw::Text(text) => text.parse().map_err(serde::de::Error::custom),
+        }
+    }
+}
+
+/// A number of CPUs, which may be fractional, e.g. `1.5`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Cpus(pub f64);
+
+impl FromStr for Cpus {
+    type Err = eyre::Report;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let cpus: f64 = s
+            .trim()
+            .parse()
+            .map_err(|_| eyre::eyre!("Invalid number of CPUs `{s}`, expected e.g. `2` or `1.5`"))?;
+        // Docker would take zero, or less, as no limit at all
+        if !cpus.is_finite() || cpus <= 0.0 {
+            eyre::bail!("Invalid number of CPUs `{s}`, it must be larger than zero");
+        }
+        Ok(Cpus(cpus))
+    }
+}
+
+impl<'de> Deserialize<'de> for Cpus {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        f64::deserialize(deserializer)?
+            .to_string()
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+/// The maximum number of processes and threads in a container.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PidsLimit(pub i64);
+
+impl FromStr for PidsLimit {
+    type Err = eyre::Report;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let pids: i64 = s
+            .trim()
+            .parse()
+            .map_err(|_| eyre::eyre!("Invalid process limit `{s}`, expected e.g. `1024`"))?;
+        // Docker would take zero, or less, as no limit at all
+        if pids <= 0 {
+            eyre::bail!("Invalid process limit `{s}`, it must be larger than zero");
+        }
+        Ok(PidsLimit(pids))
+    }
+}
+
+impl<'de> Deserialize<'de> for PidsLimit {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        i64::deserialize(deserializer)?
+            .to_string()
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A fresh temporary project root containing `files`.
+    fn project_root(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let root = std::env::temp_dir().join(format!("cargo-sandbox-config-{name}-{}", std::process::id()));
+        std::fs::create_dir_all(&root).unwrap();
+        for (path, contents) in files {
+            std::fs::write(root.join(path), contents).unwrap();
+        }
+        root
+    }
+
+    fn load_project(name: &str, files: &[(&str, &str)]) -> eyre::Result<Option<(PathBuf, Config)>> {
+        let root = project_root(name, files);
+        let loaded = Config::load_project(&root);
+        std::fs::remove_dir_all(&root).unwrap();
+        loaded
+    }
+
+    #[test]
+    fn project_config_from_cargo_sandbox_toml() {
+        let (path, config) = load_project(
+            "file",
+            &[
+                ("Cargo.toml", "[package]\nname = \"app\"\n"),
+                ("cargo-sandbox.toml", "[network]\nallow = true\n\n[[mounts]]\nsource = \"../fixtures\"\ntarget = \"/fixtures\"\n"),
+            ],
+        )
+        .unwrap()
+        .unwrap();
+        assert!(path.ends_with(PROJECT_CONFIG_FILE));
+        assert!(config.allow_network());
+        assert!(config.mounts[0].read_only, "mounts are read-only by default");
+    }
+
+    #[test]
+    fn project_config_from_package_metadata() {
+        let manifest = "[package]\nname = \"app\"\n\n[package.metadata.sandbox.project]\nread-only = true\n";
+        let (path, config) = load_project("metadata", &[("Cargo.toml", manifest)]).unwrap().unwrap();
+        assert!(path.ends_with("Cargo.toml"));
+        assert!(config.read_only_project());
+    }
+
+    #[test]
+    fn project_config_in_both_places_is_an_error() {
+        let manifest = "[package]\nname = \"app\"\n\n[package.metadata.sandbox.network]\nallow = false\n";
+        let error = load_project("both", &[("Cargo.toml", manifest), ("cargo-sandbox.toml", "")]).unwrap_err();
+        assert!(error.to_string().contains("only one of them may be used"), "{error}");
+    }
+
+    #[test]
+    fn no_project_config() {
+        assert_eq!(load_project("none", &[("Cargo.toml", "[package]\nname = \"app\"\n")]).unwrap(), None);
+    }
+
+    #[test]
+    fn unknown_keys_are_rejected() {
+        let error = parse::<Config>(Path::new("cargo-sandbox.toml"), "[network]\nallwo = true\n").unwrap_err();
+        let message = error.to_string();
+        assert!(message.contains("cargo-sandbox.toml") && message.contains("allwo"), "{message}");
+
+        let manifest = "[package]\nname = \"app\"\n\n[package.metadata.sandbox]\nnetwork-allow = true\n";
+        assert!(load_project("unknown", &[("Cargo.toml", manifest)]).is_err());
+    }
+
+    #[test]
+    fn project_values_replace_user_values_and_lists_are_appended() {
+        let user: Config = toml::from_str(
+            "docker-socket = \"/run/user.sock\"\n[network]\nallow = true\n[env]\npass = [\"HTTP_PROXY\"]\n[limits]\nmemory = \"8g\"\n",
+        )
+        .unwrap();
+        let project: Config = toml::from_str(
+            "[network]\nallow = false\n[env]\npass = [\"RUST_LOG\"]\n[[mounts]]\nsource = \"fixtures\"\ntarget = \"/fixtures\"\n",
+        )
+        .unwrap();
+
+        let mut config = Config::default();
+        config.merge(user, Path::new("/home/me/.config/cargo-sandbox/config.toml"));
+        config.merge(project, Path::new("/src/app/cargo-sandbox.toml"));
+
+        assert!(!config.allow_network());
+        assert_eq!(config.sources["network.allow"], Path::new("/src/app/cargo-sandbox.toml"));
+        // Values the project doesn't set are kept from the user configuration
+        assert_eq!(config.docker_socket.as_deref(), Some(Path::new("/run/user.sock")));
+        assert_eq!(config.limits.memory, Some(ByteSize(8 << 30)));
+        assert_eq!(config.env.pass, vec!["HTTP_PROXY".to_string(), "RUST_LOG".to_string()]);
+        // Relative mount sources are relative to the file they are configured in
+        assert_eq!(config.mounts[0].source, Path::new("/src/app/fixtures"));
+    }
+
+    #[test]
+    fn byte_size_must_be_positive() {
+        assert_eq!("512m".parse::<ByteSize>().unwrap(), ByteSize(512 << 20));
+        assert!("-1g".parse::<ByteSize>().is_err());
+        assert!("0".parse::<ByteSize>().is_err());
+        assert!(toml::from_str::<LimitsConfig>("memory = -1").is_err());
+    }
+
+    #[test]
+    fn policy_files_cover_cargo_config_up_to_the_root() {
+        let root = Path::new("/src/app");
+        let files = policy_files(root, &root.join("crates/core"));
+        for file in ["cargo-sandbox.toml", "Cargo.toml", "crates/core/.cargo/config.toml", "crates/.cargo/config", ".cargo/config.toml"] {
+            assert!(files.contains(&root.join(file)), "{file} is protected");
+        }
+        assert!(!files.contains(&PathBuf::from("/src/.cargo/config.toml")));
+    }
+
+    #[test]
+    fn cpus_and_pids_must_be_positive() {
+        assert_eq!("1.5".parse::<Cpus>().unwrap(), Cpus(1.5));
+        for cpus in ["0", "-1", "NaN", "inf"] {
+            assert!(cpus.parse::<Cpus>().is_err(), "{cpus} CPUs are rejected");
+        }
+        assert_eq!(toml::from_str::<LimitsConfig>("cpus = 2").unwrap().cpus, Some(Cpus(2.0)));
+        assert!(toml::from_str::<LimitsConfig>("cpus = -1.0").is_err());
+        assert!(toml::from_str::<LimitsConfig>("cpus = nan").is_err());
+
+        assert_eq!("1024".parse::<PidsLimit>().unwrap(), PidsLimit(1024));
+        assert!("0".parse::<PidsLimit>().is_err());
+        assert!(toml::from_str::<LimitsConfig>("pids = -1").is_err());
+    }
+
+    #[test]
+    fn command_line_limits_override_configured_ones() {
+        let mut config: Config = toml::from_str("[limits]\nmemory = \"8g\"\npids = 1024\n").unwrap();
+        config.override_limits(LimitsConfig {
+            memory: Some(ByteSize(1 << 30)),
+            ..Default::default()
+        });
+        assert_eq!(config.limits.memory, Some(ByteSize(1 << 30)));
+        assert_eq!(config.pids_limit(), 1024);
+        assert_eq!(config.sources["limits.memory"], Path::new(COMMAND_LINE));
+    }
+}
@@ -11,4 +11,66 @@ impl ContainerType {
             ContainerType::Publish => "publish",
         }
     }
+
+    /// The image built from `dockerfile()`, tagged with our version so that an upgrade
+    /// never runs with images built for another version.
+    pub fn default_image(&self) -> String {
+        format!("cargo-sandbox-{}:{}", self.as_str(), env!("CARGO_PKG_VERSION"))
+    }
+
+    /// The Dockerfile of `default_image()`, embedded at compile time.
+    pub fn dockerfile(&self) -> &'static str {
+        match self {
+            ContainerType::Build => include_str!("../cargo-sandbox-build.Dockerfile"),
+            ContainerType::Publish => include_str!("../cargo-sandbox-publish.Dockerfile"),
+        }
+    }
+
+    /// The seccomp profile for this container type, embedded at compile time.
+    /// See `static/seccomp/README.md` for how these differ from Docker's default.
+    pub fn seccomp_profile(&self) -> &'static str {
+        match self {
+            ContainerType::Build => include_str!("../static/seccomp/build.json"),
+            ContainerType::Publish => include_str!("../static/seccomp/publish.json"),
+        }
+    }
+
+    /// The AppArmor profile for this container type, if there is one, as
+    /// `(profile name, profile source)`.
+    ///
+    /// AppArmor profiles have to be loaded into the host kernel before the daemon
+    /// can apply them, so the source is embedded mostly so that we can point the
+    /// user at it when the daemon rejects the profile.
+    pub fn apparmor_profile(&self) -> Option<(&'static str, &'static str)> {
+        match self {
+            ContainerType::Build => Some((
+                "cargo-sandbox-build",
+                include_str!("../static/apparmor/build.aa-profile"),
+            )),
+            ContainerType::Publish => None,
+        }
+    }
+
+    /// The `SecurityOpt` entries to apply to every container of this type. Without
+    /// `no-new-privileges`, a setuid binary in the image could regain the capabilities
+    /// that are dropped.
+    pub fn security_opts(&self, no_new_privileges: bool) -> Vec<String> {
+        let mut security_opts = vec![format!("seccomp={}", self.seccomp_profile())];
+        if let Some((name, _)) = self.apparmor_profile() {
+            security_opts.push(format!("apparmor={name}"));
+        }
+        if no_new_privileges {
+            security_opts.push("no-new-privileges".to_string());
+        }
+        security_opts
+    }
+
+    /// The directories that stay writable when the root filesystem is read-only, as tmpfs
+    /// mounts. Everything else that has to be written to is a volume or the project.
+    pub fn tmpfs_dirs(&self) -> &'static [&'static str] {
+        match self {
+            ContainerType::Build => &["/tmp", "/home/cargo-sandbox-user/.cache"],
+            ContainerType::Publish => &["/tmp"],
+        }
+    }
 }
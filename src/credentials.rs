@@ -0,0 +1,161 @@
+use std::collections::BTreeMap;
+use std::fs::{DirBuilder, OpenOptions};
+use std::io::Write;
+use std::os::unix::fs::{DirBuilderExt, OpenOptionsExt};
+use std::path::{Path, PathBuf};
+
+use eyre::WrapErr;
+use serde::{Deserialize, Serialize};
+
+use crate::cache::DOCKER_CARGO_HOME;
+use crate::config::read_optional;
+use crate::dockerapi::mount::Mount;
+
+/// The credential store, relative to `$XDG_CONFIG_HOME`. It is deliberately not cargo's
+/// `~/.cargo/credentials.toml`, which every cargo command on the host can read.
+pub const CREDENTIALS_FILE: &str = "cargo-sandbox/credentials.toml";
+
+/// Registry tokens, in the same format as cargo's `credentials.toml`, so that the
+/// part for one registry can be handed to cargo in the Publish container as is.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Credentials {
+    /// The token for crates.io.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub registry: Option<RegistryToken>,
+    /// Tokens for alternative registries, by name.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub registries: BTreeMap<String, RegistryToken>,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct RegistryToken {
+    pub token: String,
+}
+
+impl Credentials {
+    pub fn load() -> eyre::Result<Credentials> {
+        let path = credentials_path()?;
+        match read_optional(&path)? {
+            Some(contents) => toml::from_str(&contents)
+                .map_err(|e| eyre::eyre!("Invalid credentials in {}\n{e}", path.display())),
+            None => Ok(Credentials::default()),
+        }
+    }
+
+    /// Stores `token` for `registry`, or for crates.io if it is `None`. The store is only
+    /// readable by the current user.
+    pub fn store(registry: Option<&str>, token: String) -> eyre::Result<PathBuf> {
+        let mut credentials = Credentials::load()?;
+        let token = RegistryToken { token };
+        match registry {
+            Some(registry) => {
+                credentials.registries.insert(registry.to_string(), token);
+            }
+            None => credentials.registry = Some(token),
+        }
+
+        let path = credentials_path()?;
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        write_file(&path, &toml::to_string(&credentials)?, 0o600)?;
+        Ok(path)
+    }
+
+    /// Only the token for `registry`, so that the Publish container can't read the others.
+    fn only(&self, registry: Option<&str>) -> Option<Credentials> {
+        match registry {
+            Some(registry) => {
+                let token = self.registries.get(registry)?.clone();
+                Some(Credentials {
+                    registry: None,
+                    registries: BTreeMap::from([(registry.to_string(), token)]),
+                })
+            }
+            None => Some(Credentials {
+                registry: Some(self.registry.clone()?),
+                registries: BTreeMap::new(),
+            }),
+        }
+    }
+}
+
+fn credentials_path() -> eyre::Result<PathBuf> {
+    let config_home = std::env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")));
+    match config_home {
+        Some(config_home) => Ok(config_home.join(CREDENTIALS_FILE)),
+        None => eyre::bail!("Neither XDG_CONFIG_HOME nor HOME is set, so there is nowhere to keep credentials"),
+    }
+}
+
+fn write_file(path: &Path, contents: &str, mode: u32) -> eyre::Result<()> {
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(mode)
+        .open(path)
+        .wrap_err_with(|| format!("Failed to open {}", path.display()))?;
+    file.write_all(contents.as_bytes())?;
+    Ok(())
+}
+
+/// A copy of one registry's token for a single Publish container. It lives in a private
+/// directory on a tmpfs if the host has one, is mounted read-only as cargo's
+/// `credentials.toml`, and is deleted when this is dropped.
+pub struct PublishCredentials {
+    dir: PathBuf,
+    file: PathBuf,
+}
+
+impl PublishCredentials {
+    pub fn create(registry: Option<&str>) -> eyre::Result<PublishCredentials> {
+        let Some(selected) = Credentials::load()?.only(registry) else {
+            eyre::bail!(
+                "No token for {}, run `cargo-sandbox login{}` first",
+                registry.unwrap_or("crates.io"),
+                registry.map(|registry| format!(" --registry {registry}")).unwrap_or_default(),
+            );
+        };
+
+        // `XDG_RUNTIME_DIR` is a per-user tmpfs, so the token never touches the disk
+        let base = std::env::var_os("XDG_RUNTIME_DIR")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .unwrap_or_else(std::env::temp_dir);
+        let dir = base.join(format!("cargo-sandbox-publish-{}", std::process::id()));
+        DirBuilder::new()
+            .mode(0o700)
+            .create(&dir)
+            .wrap_err_with(|| format!("Failed to create {}", dir.display()))?;
+
+        let credentials = PublishCredentials {
+            file: dir.join("credentials.toml"),
+            dir,
+        };
+        // The directory keeps other users out. The file itself has to be readable by the
+        // container's user, whose uid needn't match ours.
+        write_file(&credentials.file, &toml::to_string(&selected)?, 0o644)?;
+        Ok(credentials)
+    }
+
+    pub fn mount(&self) -> eyre::Result<Mount> {
+        Ok(Mount {
+            read_only: true,
+            ..Mount::bind(&self.file, format!("{DOCKER_CARGO_HOME}/credentials.toml"))?
+        })
+    }
+}
+
+impl Drop for PublishCredentials {
+    fn drop(&mut self) {
+        if let Err(e) = std::fs::remove_dir_all(&self.dir) {
+            eprintln!("Failed to remove {}: {e}", self.dir.display());
+        }
+    }
+}
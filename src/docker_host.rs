@@ -0,0 +1,204 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use eyre::WrapErr;
+use serde::Deserialize;
+
+use crate::config::Config;
+
+const DEFAULT_DOCKER_SOCKET: &str = "/var/run/docker.sock";
+
+/// Resolves the unix socket of the docker daemon to use, in order of precedence:
+///
+/// 1. The `--docker-host` flag
+/// 2. The `DOCKER_HOST` environment variable
+/// 3. The active docker context, from `DOCKER_CONTEXT` or `~/.docker/config.json`
+/// 4. The `docker-socket` setting
+/// 5. The first socket that exists out of `/var/run/docker.sock`, rootless docker's
+///    `$XDG_RUNTIME_DIR/docker.sock` and podman's `$XDG_RUNTIME_DIR/podman/podman.sock`
+pub fn resolve_docker_socket(flag: Option<&str>, config: &Config) -> eyre::Result<PathBuf> {
+    if let Some(host) = flag {
+        return parse_host(host).wrap_err("Invalid --docker-host");
+    }
+
+    if let Some(host) = non_empty_var("DOCKER_HOST") {
+        return parse_host(&host).wrap_err("Invalid DOCKER_HOST");
+    }
+
+    if let Some((context, host)) = context_host()? {
+        return parse_host(&host).wrap_err_with(|| format!("Invalid endpoint for docker context `{context}`"));
+    }
+
+    if let Some(socket) = &config.docker_socket {
+        return Ok(socket.clone());
+    }
+
+    Ok(default_socket())
+}
+
+/// Accepts `unix:///path/to/socket` or a bare path. The client only speaks HTTP over unix
+/// sockets, so other schemes are rejected rather than silently ignored.
+fn parse_host(host: &str) -> eyre::Result<PathBuf> {
+    match host.split_once("://") {
+        Some(("unix", path)) => Ok(PathBuf::from(path)),
+        Some((scheme, _)) => eyre::bail!(
+            "`{host}` is a `{scheme}://` endpoint, but only `unix://` endpoints are supported"
+        ),
+        None => Ok(PathBuf::from(host)),
+    }
+}
+
+fn default_socket() -> PathBuf {
+    let mut candidates = vec![PathBuf::from(DEFAULT_DOCKER_SOCKET)];
+    if let Some(runtime_dir) = std::env::var_os("XDG_RUNTIME_DIR").map(PathBuf::from) {
+        candidates.push(runtime_dir.join("docker.sock"));
+        candidates.push(runtime_dir.join("podman").join("podman.sock"));
+    }
+    candidates
+        .iter()
+        .find(|candidate| candidate.exists())
+        .cloned()
+        .unwrap_or_else(|| PathBuf::from(DEFAULT_DOCKER_SOCKET))
+}
+
+fn non_empty_var(key: &str) -> Option<String> {
+    std::env::var(key).ok().filter(|value| !value.is_empty())
+}
+
+#[derive(Deserialize)]
+struct DockerConfigFile {
+    #[serde(rename = "currentContext", default)]
+    current_context: String,
+}
+
+#[derive(Deserialize)]
+struct ContextMeta {
+    #[serde(rename = "Name")]
+    name: String,
+    #[serde(rename = "Endpoints", default)]
+    endpoints: HashMap<String, ContextEndpoint>,
+}
+
+#[derive(Deserialize)]
+struct ContextEndpoint {
+    #[serde(rename = "Host")]
+    host: Option<String>,
+}
+
+/// The name and docker endpoint of the active docker context, unless it is the default
+/// context, which has no endpoint of its own.
+fn context_host() -> eyre::Result<Option<(String, String)>> {
+    let docker_config_dir = match std::env::var_os("DOCKER_CONFIG") {
+        Some(dir) => PathBuf::from(dir),
+        None => match std::env::var_os("HOME") {
+            Some(home) => PathBuf::from(home).join(".docker"),
+            None => return Ok(None),
+        },
+    };
+
+    context_endpoint(&docker_config_dir, non_empty_var("DOCKER_CONTEXT"))
+}
+
+/// The endpoint of `context`, or of the current context in `docker_config_dir` if it is `None`.
+fn context_endpoint(docker_config_dir: &Path, context: Option<String>) -> eyre::Result<Option<(String, String)>> {
+    let context = match context {
+        Some(context) => context,
+        None => current_context(docker_config_dir)?,
+    };
+    if context.is_empty() || context == "default" {
+        return Ok(None);
+    }
+
+    // Context metadata lives in a directory named after the digest of the context's name,
+    // so rather than hashing the name we look for the context with a matching `Name`.
+    let meta_dir = docker_config_dir.join("contexts").join("meta");
+    let entries = std::fs::read_dir(&meta_dir)
+        .wrap_err_with(|| format!("docker context `{context}` is active, but {} can't be read", meta_dir.display()))?;
+    for entry in entries {
+        let meta_path = entry?.path().join("meta.json");
+        let Ok(contents) = std::fs::read(&meta_path) else {
+            continue;
+        };
+        let meta: ContextMeta = serde_json::from_slice(&contents)
+            .wrap_err_with(|| format!("Invalid docker context metadata in {}", meta_path.display()))?;
+        if meta.name != context {
+            continue;
+        }
+        return match meta.endpoints.get("docker").and_then(|endpoint| endpoint.host.clone()) {
+            Some(host) => Ok(Some((context, host))),
+            None => eyre::bail!("docker context `{context}` has no docker endpoint"),
+        };
+    }
+
+    eyre::bail!("docker context `{context}` is active, but it does not exist in {}", meta_dir.display())
+}
+
+fn current_context(docker_config_dir: &Path) -> eyre::Result<String> {
+    let config_path = docker_config_dir.join("config.json");
+    match std::fs::read(&config_path) {
+        Ok(contents) => {
+            let config: DockerConfigFile = serde_json::from_slice(&contents)
+                .wrap_err_with(|| format!("Invalid docker configuration in {}", config_path.display()))?;
+            Ok(config.current_context)
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(String::new()),
+        Err(e) => Err(eyre::eyre!("Failed to read {}: {e}", config_path.display())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unix_hosts_and_bare_paths() {
+        assert_eq!(parse_host("unix:///run/user/1000/docker.sock").unwrap(), Path::new("/run/user/1000/docker.sock"));
+        assert_eq!(parse_host("/var/run/docker.sock").unwrap(), Path::new("/var/run/docker.sock"));
+    }
+
+    #[test]
+    fn other_schemes_are_rejected() {
+        let error = parse_host("tcp://127.0.0.1:2375").unwrap_err();
+        assert!(error.to_string().contains("only `unix://` endpoints are supported"), "{error}");
+        assert!(parse_host("ssh://me@build-host").is_err());
+    }
+
+    /// A docker configuration directory with `current_context` selected and a context named
+    /// `colima`, stored the way the docker CLI stores it.
+    fn docker_config_dir(name: &str, current_context: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("cargo-sandbox-docker-{name}-{}", std::process::id()));
+        let meta_dir = dir.join("contexts/meta/a1b2c3");
+        std::fs::create_dir_all(&meta_dir).unwrap();
+        std::fs::write(dir.join("config.json"), format!(r#"{{"currentContext": "{current_context}"}}"#)).unwrap();
+        std::fs::write(
+            meta_dir.join("meta.json"),
+            r#"{"Name": "colima", "Endpoints": {"docker": {"Host": "unix:///home/me/.colima/docker.sock"}}}"#,
+        )
+        .unwrap();
+        dir
+    }
+
+    #[test]
+    fn current_context_is_resolved_by_name() {
+        let dir = docker_config_dir("current", "colima");
+        let endpoint = context_endpoint(&dir, None);
+        let default = context_endpoint(&dir, Some("default".to_string()));
+        let missing = context_endpoint(&dir, Some("missing".to_string()));
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(
+            endpoint.unwrap(),
+            Some(("colima".to_string(), "unix:///home/me/.colima/docker.sock".to_string())),
+        );
+        assert_eq!(default.unwrap(), None, "DOCKER_CONTEXT=default overrides the current context");
+        assert!(missing.is_err());
+    }
+
+    #[test]
+    fn default_context_has_no_endpoint() {
+        let dir = docker_config_dir("default", "");
+        let endpoint = context_endpoint(&dir, None);
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(endpoint.unwrap(), None);
+    }
+}
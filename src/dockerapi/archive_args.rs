@@ -0,0 +1,8 @@
+use serde::Serialize;
+
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct ArchiveArgs {
+    /// Resource in the container's filesystem to archive, or the directory to extract an archive into.
+    #[serde(rename = "path")]
+    pub path: String,
+}
@@ -0,0 +1,20 @@
+use serde::Serialize;
+
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct BuildImageArgs {
+    /// Path within the build context to the Dockerfile.
+    #[serde(rename = "dockerfile")]
+    pub dockerfile: String,
+    /// A name and optional tag to apply to the image in the `name:tag` format.
+    #[serde(rename = "t")]
+    pub tag: String,
+    /// JSON map of string pairs for build-time variables.
+    #[serde(rename = "buildargs", skip_serializing_if = "Option::is_none")]
+    pub build_args: Option<String>,
+    /// JSON map of string pairs for labels to set on the image.
+    #[serde(rename = "labels", skip_serializing_if = "Option::is_none")]
+    pub labels: Option<String>,
+    /// Always remove intermediate containers, even upon failure.
+    #[serde(rename = "forcerm")]
+    pub force_rm: bool,
+}
@@ -1,71 +1,110 @@
-use std::path::Path;
+use std::io::Write;
+use std::path::{Path, PathBuf};
 
-use eyre::Context;
 use futures::{Stream, StreamExt};
+use hyper::upgrade::Upgraded;
 use hyper::Client as HyperClient;
 use hyper::{Body, Uri};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt};
 
+use crate::dockerapi::archive_args::ArchiveArgs;
+use crate::dockerapi::build_image_args::BuildImageArgs;
+use crate::dockerapi::container_inspect::ContainerInspect;
 use crate::dockerapi::container_summary::ContainerSummary;
+use crate::dockerapi::container_wait_response::ContainerWaitResponse;
 use crate::dockerapi::create_container_args::CreateContainerArgs;
 use crate::dockerapi::create_container_response::CreateContainerResponse;
 use crate::dockerapi::create_exec_args::CreateExecArgs;
 use crate::dockerapi::create_exec_response::CreateExecResponse;
+use crate::dockerapi::create_image_args::CreateImageArgs;
+use crate::dockerapi::create_volume_args::CreateVolumeArgs;
+use crate::dockerapi::errors::DockerError;
+use crate::dockerapi::image_inspect::ImageInspect;
+use crate::dockerapi::json_message::JsonMessage;
+use crate::dockerapi::kill_container_args::KillContainerArgs;
 use crate::dockerapi::list_containers::{ListContainersArgs, ListContainersResponse};
+use crate::dockerapi::list_volumes::{ListVolumesArgs, ListVolumesResponse};
+use crate::dockerapi::resize_args::ResizeArgs;
 use crate::dockerapi::start_exec_args::StartExecArgs;
 use crate::dockerapi::start_exec_response::StartExecResponse;
+use crate::dockerapi::stop_container_args::StopContainerArgs;
 use crate::dockerapi::unix_connector::UnixSocketConnector;
+use crate::dockerapi::volume::Volume;
+
+/// Every request names the API version it was written against. A daemon that doesn't support
+/// it refuses the request, rather than serving its own version and leaving fields unset.
+const API_BASE: &str = "http://localhost/v1.41";
 
 #[derive(Clone)]
 pub struct Client {
     inner_client: HyperClient<UnixSocketConnector, Body>,
+    socket: PathBuf,
 }
 
 impl Client {
     /// Create a new client for a local docker API.
     /// ```
-    /// fn main() {
-    ///     Client::local("/var/run/docker.sock")
-    /// }
+    /// let client = Client::local("/var/run/docker.sock");
     /// ```
     pub fn local<P: AsRef<Path>>(path: P) -> Self {
-        let connector: UnixSocketConnector = UnixSocketConnector::new(path);
+        let connector: UnixSocketConnector = UnixSocketConnector::new(&path);
         Self {
             inner_client: HyperClient::builder().build(connector),
+            socket: path.as_ref().to_path_buf(),
         }
     }
 
-    pub async fn attach(&self, container_id: &str) -> eyre::Result<()> {
-        let client = &self.inner_client;
-        let uri = format!("http://localhost/containers/{}/attach?stream=1&stdout=1&stdin=1&stderr=1", container_id).parse::<Uri>()?;
+    /// Attach to a container's stdin, stdout and stderr. The connection is upgraded to a
+    /// raw stream, so the container must be started only after this returns for none of
+    /// its output to be lost.
+    pub async fn attach(&self, container_id: &str) -> Result<AttachedContainer, DockerError> {
+        let uri = format!("{API_BASE}/containers/{}/attach?stream=1&stdout=1&stdin=1&stderr=1", container_id).parse::<Uri>()?;
 
         let request = hyper::Request::post(uri)
-            // .header("Content-Type", "application/json")
+            .header(hyper::header::CONNECTION, "Upgrade")
+            .header(hyper::header::UPGRADE, "tcp")
             .body(Body::empty())?;
 
-        let res = client.request(request).await?;
-
-        let mut body = res.into_body();
-        print_docker_encoded_stream(body).await?;
+        let res = self.send(request).await?;
+        if res.status() != hyper::StatusCode::SWITCHING_PROTOCOLS {
+            let status = res.status();
+            let body = read_body_to_vec(res).await?;
+            return Err(DockerError::from_response(status, &body));
+        }
 
-        Ok(())
+        let upgraded = hyper::upgrade::on(res).await?;
+        Ok(AttachedContainer { upgraded })
     }
 
     pub async fn create_container(
         &self,
         args: CreateContainerArgs,
-    ) -> eyre::Result<CreateContainerResponse> {
-        let client = &self.inner_client;
-        let uri = Uri::from_static("http://localhost/containers/create");
+    ) -> Result<CreateContainerResponse, DockerError> {
+        let uri = format!("{API_BASE}/containers/create").parse::<Uri>()?;
 
         let request = hyper::Request::post(uri)
             .header("Content-Type", "application/json")
-            .body(Body::from(serde_json::to_vec(&args)?))?;
+            .body(json_body(&args)?)?;
+
+        let res = error_for_status(self.send(request).await?).await?;
+        let body = read_body_to_vec(res).await?;
 
-        let res = client.request(request).await?;
+        parse_body("CreateContainerResponse", &body)
+    }
 
+    /// Low-level information about a container, such as whether it was killed for running
+    /// out of memory.
+    pub async fn inspect_container(&self, container_id: &str) -> Result<ContainerInspect, DockerError> {
+        let uri = format!("{API_BASE}/containers/{}/json", container_id).parse::<Uri>()?;
+
+        let request = hyper::Request::get(uri).body(Body::empty())?;
+
+        let res = error_for_status(self.send(request).await?).await?;
         let body = read_body_to_vec(res).await?;
 
-        Ok(serde_json::from_slice(&body)?)
+        parse_body("ContainerInspect", &body)
     }
 
     pub async fn remove_container(
@@ -73,71 +112,141 @@ impl Client {
         container_id: String,
         force: bool,
         remove_anonymouse_volumes: bool,
-    ) -> eyre::Result<()> {
+    ) -> Result<(), DockerError> {
+        // These are query parameters, the daemon ignores a request body
+        let uri = format!(
+            "{API_BASE}/containers/{}?force={}&v={}",
+            container_id, force, remove_anonymouse_volumes
+        )
+        .parse::<Uri>()?;
+
+        let request = hyper::Request::delete(uri).body(Body::empty())?;
 
-        let client = &self.inner_client;
-        let uri = format!("http://localhost/containers/{}", container_id).parse::<Uri>()?;
+        error_for_status(self.send(request).await?).await?;
+        Ok(())
+    }
 
-        let request = hyper::Request::delete(uri)
+    pub async fn create_volume(&self, args: CreateVolumeArgs) -> Result<Volume, DockerError> {
+        let uri = format!("{API_BASE}/volumes/create").parse::<Uri>()?;
+
+        let request = hyper::Request::post(uri)
             .header("Content-Type", "application/json")
-            .body(
-            Body::from(serde_json::to_vec(&serde_json::json!({
-                "force": force,
-                "remove_anonymouse_volumes": remove_anonymouse_volumes,
-            })).unwrap())
-        )?;
-
-        let res = client.request(request).await?;
+            .body(json_body(&args)?)?;
+
+        let res = error_for_status(self.send(request).await?).await?;
+        let body = read_body_to_vec(res).await?;
+
+        parse_body("Volume", &body)
+    }
+
+    pub async fn list_volumes(&self, args: ListVolumesArgs) -> Result<Vec<Volume>, DockerError> {
+        let args = serde_url_params::to_string(&args)?;
+        let uri: Uri = format!("{API_BASE}/volumes?{}", args).parse()?;
+
+        let request = hyper::Request::get(uri).body(Body::empty())?;
+
+        let res = error_for_status(self.send(request).await?).await?;
+        let body = read_body_to_vec(res).await?;
+
+        let response: ListVolumesResponse = parse_body("ListVolumesResponse", &body)?;
+        Ok(response.volumes.unwrap_or_default())
+    }
+
+    pub async fn remove_volume(&self, name: &str, force: bool) -> Result<(), DockerError> {
+        let uri = format!("{API_BASE}/volumes/{}?force={}", name, force).parse::<Uri>()?;
+
+        let request = hyper::Request::delete(uri).body(Body::empty())?;
+
+        error_for_status(self.send(request).await?).await?;
         Ok(())
     }
 
-    pub async fn start_container(&self, container_id: &str) -> eyre::Result<()> {
-        let client = &self.inner_client;
-        let uri = format!("http://localhost/containers/{}/start", container_id).parse::<Uri>()?;
+    /// Get a tar archive of a resource in the filesystem of a container. The container
+    /// does not need to be running.
+    pub async fn get_archive(&self, container_id: &str, args: ArchiveArgs) -> Result<Vec<u8>, DockerError> {
+        let args = serde_url_params::to_string(&args)?;
+        let uri = format!("{API_BASE}/containers/{}/archive?{}", container_id, args).parse::<Uri>()?;
 
-        let request = hyper::Request::post(uri).body(Body::empty())?;
+        let request = hyper::Request::get(uri).body(Body::empty())?;
 
-        let res = client.request(request).await?;
-        if !res.status().is_success() {
-            let body = read_body_to_vec(res).await?;
-            // todo: This is a json response with a `message` field
-            eyre::bail!("start_container: {:?}", String::from_utf8_lossy(&body));
-        }
+        let res = error_for_status(self.send(request).await?).await?;
+        read_body_to_vec(res).await
+    }
+
+    /// Extract a tar archive into a directory in the filesystem of a container. The
+    /// container does not need to be running.
+    pub async fn put_archive(&self, container_id: &str, args: ArchiveArgs, archive: Vec<u8>) -> Result<(), DockerError> {
+        let args = serde_url_params::to_string(&args)?;
+        let uri = format!("{API_BASE}/containers/{}/archive?{}", container_id, args).parse::<Uri>()?;
+
+        let request = hyper::Request::put(uri)
+            .header("Content-Type", "application/x-tar")
+            .body(Body::from(archive))?;
 
+        error_for_status(self.send(request).await?).await?;
         Ok(())
     }
 
-    pub async fn stop_container(&self, container_id: &str) -> eyre::Result<()> {
-        let client = &self.inner_client;
-        let uri = format!("http://localhost/containers/{}/kill", container_id).parse::<Uri>()?;
+    pub async fn start_container(&self, container_id: &str) -> Result<(), DockerError> {
+        let uri = format!("{API_BASE}/containers/{}/start", container_id).parse::<Uri>()?;
 
-        let request = hyper::Request::post(uri)
-            .header("Content-Type", "application/json")
-            .body(Body::empty())?;
+        let request = hyper::Request::post(uri).body(Body::empty())?;
+
+        error_for_status(self.send(request).await?).await?;
+        Ok(())
+    }
 
-        let res = client.request(request).await?;
+    /// Resize the TTY of a running container.
+    pub async fn resize_container(&self, container_id: &str, args: ResizeArgs) -> Result<(), DockerError> {
+        let args = serde_url_params::to_string(&args)?;
+        let uri = format!("{API_BASE}/containers/{}/resize?{}", container_id, args).parse::<Uri>()?;
 
-        let body = read_body_to_vec(res).await?;
-        // println!("{}", serde_json::from_slice::<serde_json::Value>(&body)?);
+        let request = hyper::Request::post(uri).body(Body::empty())?;
 
-        Ok(serde_json::from_slice(&body)?)
+        error_for_status(self.send(request).await?).await?;
+        Ok(())
     }
 
-    pub async fn wait(&self, container_id: String) -> eyre::Result<()> {
-        let client = &self.inner_client;
-        let uri = format!("http://localhost/containers/{}/wait", container_id).parse::<Uri>()?;
+    /// Send a signal to a container, which defaults to `SIGKILL`.
+    pub async fn kill_container(&self, container_id: &str, args: KillContainerArgs) -> Result<(), DockerError> {
+        let args = serde_url_params::to_string(&args)?;
+        let uri = format!("{API_BASE}/containers/{}/kill?{}", container_id, args).parse::<Uri>()?;
 
         let request = hyper::Request::post(uri).body(Body::empty())?;
 
-        let res = client.request(request).await?;
+        error_for_status(self.send(request).await?).await?;
+        Ok(())
+    }
 
-        let body = read_body_to_vec(res).await?;
-        // println!("{}", serde_json::from_slice::<serde_json::Value>(&body)?);
+    /// Stop a container with `args.signal`, or the container's stop signal, and kill it
+    /// if it is still running after `args.timeout` seconds.
+    pub async fn stop_container(&self, container_id: &str, args: StopContainerArgs) -> Result<(), DockerError> {
+        let args = serde_url_params::to_string(&args)?;
+        let uri = format!("{API_BASE}/containers/{}/stop?{}", container_id, args).parse::<Uri>()?;
+
+        let request = hyper::Request::post(uri).body(Body::empty())?;
+
+        let res = self.send(request).await?;
+        // 304 means that the container was already stopped
+        if res.status() != hyper::StatusCode::NOT_MODIFIED {
+            error_for_status(res).await?;
+        }
 
         Ok(())
     }
 
-    pub async fn exec(&self, container_id: String, args: CreateExecArgs) -> eyre::Result<()> {
+    pub async fn wait(&self, container_id: String) -> Result<ContainerWaitResponse, DockerError> {
+        let uri = format!("{API_BASE}/containers/{}/wait", container_id).parse::<Uri>()?;
+
+        let request = hyper::Request::post(uri).body(Body::empty())?;
+
+        let res = error_for_status(self.send(request).await?).await?;
+        let body = read_body_to_vec(res).await?;
+
+        parse_body("ContainerWaitResponse", &body)
+    }
+
+    pub async fn exec(&self, container_id: String, args: CreateExecArgs) -> Result<(), DockerError> {
         let exec_id = self.create_exec(container_id, args).await?;
         self.start_exec(
             &exec_id.id,
@@ -154,41 +263,38 @@ impl Client {
         &self,
         container_id: String,
         args: CreateExecArgs,
-    ) -> eyre::Result<CreateExecResponse> {
-        let client = &self.inner_client;
-        let uri = format!("http://localhost/containers/{}/exec", container_id).parse::<Uri>()?;
+    ) -> Result<CreateExecResponse, DockerError> {
+        let uri = format!("{API_BASE}/containers/{}/exec", container_id).parse::<Uri>()?;
 
         let request = hyper::Request::post(uri)
             .header("Content-Type", "application/json")
-            .body(Body::from(serde_json::to_vec(&args)?))?;
-
-        let res = client.request(request).await?;
+            .body(json_body(&args)?)?;
 
+        let res = error_for_status(self.send(request).await?).await?;
         let body = read_body_to_vec(res).await?;
 
         // println!(
         //     "create_exec {}",
         //     serde_json::from_slice::<serde_json::Value>(&body)?
         // );
-        Ok(serde_json::from_slice(&body)?)
+        parse_body("CreateExecResponse", &body)
     }
 
     pub async fn start_exec(
         &self,
         exec_id: &str,
         args: StartExecArgs,
-    ) -> eyre::Result<StartExecResponse> {
-        let client = &self.inner_client;
-        let uri = format!("http://localhost/exec/{}/start", exec_id).parse::<Uri>()?;
+    ) -> Result<StartExecResponse, DockerError> {
+        let uri = format!("{API_BASE}/exec/{}/start", exec_id).parse::<Uri>()?;
 
         let request = hyper::Request::post(uri)
             .header("Content-Type", "application/json")
-            .body(Body::from(serde_json::to_vec(&args)?))?;
+            .body(json_body(&args)?)?;
 
-        let res = client.request(request).await?;
+        let res = error_for_status(self.send(request).await?).await?;
 
         let mut body = res.into_body();
-        print!("\n");
+        println!();
         while let Some(chunk) = body.next().await {
             let chunk = chunk?;
             if chunk.is_empty() {
@@ -203,20 +309,126 @@ impl Client {
     pub async fn list_containers(
         &self,
         args: ListContainersArgs,
-    ) -> eyre::Result<ListContainersResponse> {
-        let client = &self.inner_client;
-
+    ) -> Result<ListContainersResponse, DockerError> {
         let args = serde_url_params::to_string(&args)?;
         // println!("\nargs= {}\n", args);
-        let uri: Uri = format!("http://localhost/containers/json?{}", args).parse()?;
-        let res = client.get(uri).await?;
+        let uri: Uri = format!("{API_BASE}/containers/json?{}", args).parse()?;
+
+        let request = hyper::Request::get(uri).body(Body::empty())?;
 
+        let res = error_for_status(self.send(request).await?).await?;
         let body = read_body_to_vec(res).await?;
         // println!("{}", serde_json::from_slice::<serde_json::Value>(&body)?);
-        let containers: Vec<ContainerSummary> =
-            serde_json::from_slice(&body).context("ListContainersResponse")?;
+        let containers: Vec<ContainerSummary> = parse_body("ListContainersResponse", &body)?;
         Ok(ListContainersResponse { containers })
     }
+
+    /// Return low-level information about an image.
+    pub async fn inspect_image(&self, name: &str) -> Result<ImageInspect, DockerError> {
+        let uri = format!("{API_BASE}/images/{}/json", name).parse::<Uri>()?;
+
+        let request = hyper::Request::get(uri).body(Body::empty())?;
+
+        let res = error_for_status(self.send(request).await?).await?;
+        let body = read_body_to_vec(res).await?;
+
+        parse_body("ImageInspect", &body)
+    }
+
+    /// Pull an image from a registry, passing each progress message to `on_progress`.
+    pub async fn pull_image(
+        &self,
+        args: CreateImageArgs,
+        on_progress: impl FnMut(&JsonMessage),
+    ) -> Result<(), DockerError> {
+        let image = format!("{}:{}", args.from_image, args.tag);
+        let args = serde_url_params::to_string(&args)?;
+        let uri = format!("{API_BASE}/images/create?{}", args).parse::<Uri>()?;
+
+        let request = hyper::Request::post(uri).body(Body::empty())?;
+
+        let res = error_for_status(self.send(request).await?).await?;
+        read_progress(res, on_progress)
+            .await?
+            .map_err(|message| DockerError::PullFailed { image, message })
+    }
+
+    /// Build an image from a tar archive of its build context, passing each progress
+    /// message to `on_progress`.
+    pub async fn build_image(
+        &self,
+        args: BuildImageArgs,
+        context: Vec<u8>,
+        on_progress: impl FnMut(&JsonMessage),
+    ) -> Result<(), DockerError> {
+        let image = args.tag.clone();
+        let args = serde_url_params::to_string(&args)?;
+        let uri = format!("{API_BASE}/build?{}", args).parse::<Uri>()?;
+
+        let request = hyper::Request::post(uri)
+            .header("Content-Type", "application/x-tar")
+            .body(Body::from(context))?;
+
+        let res = error_for_status(self.send(request).await?).await?;
+        read_progress(res, on_progress)
+            .await?
+            .map_err(|message| DockerError::BuildFailed { image, message })
+    }
+
+    /// Sends a request to the daemon. Failing to connect at all usually means that the
+    /// daemon isn't running, or that we're looking at the wrong socket.
+    async fn send(&self, request: hyper::Request<Body>) -> Result<hyper::Response<Body>, DockerError> {
+        self.inner_client.request(request).await.map_err(|source| {
+            if source.is_connect() {
+                DockerError::DaemonUnavailable {
+                    socket: self.socket.clone(),
+                    source,
+                }
+            } else {
+                DockerError::Request(source)
+            }
+        })
+    }
+}
+
+/// The raw stream of an attached container.
+pub struct AttachedContainer {
+    upgraded: Upgraded,
+}
+
+impl AttachedContainer {
+    /// Prints the container's output until it exits. With `forward_stdin`, the host's stdin
+    /// is copied to the container, and the container's stdin is closed when ours is.
+    ///
+    /// Containers with a TTY send their output as is, otherwise stdout and stderr are
+    /// multiplexed into one stream.
+    pub async fn forward(self, forward_stdin: bool, tty: bool) -> eyre::Result<()> {
+        let (reader, mut writer) = tokio::io::split(self.upgraded);
+
+        let stdin = forward_stdin.then(|| {
+            tokio::spawn(async move {
+                let mut stdin = tokio::io::stdin();
+                tokio::io::copy(&mut stdin, &mut writer).await?;
+                // Half-close the connection so that the container sees EOF on its stdin
+                writer.shutdown().await?;
+                Ok::<(), eyre::Error>(())
+            })
+        });
+
+        let result = if tty {
+            print_raw_stream(reader).await
+        } else {
+            print_docker_encoded_stream(reader).await
+        };
+
+        // Reading stdin blocks until the user enters something, which they won't once the
+        // container has exited
+        if let Some(stdin) = stdin {
+            stdin.abort();
+        }
+
+        result
+    }
 }
 
 fn body_size_hint(body: &Body) -> usize {
@@ -224,7 +436,7 @@ fn body_size_hint(body: &Body) -> usize {
     hint.1.unwrap_or(std::cmp::max(hint.0, 16))
 }
 
-async fn read_body_to_vec(res: hyper::Response<Body>) -> eyre::Result<Vec<u8>> {
+async fn read_body_to_vec(res: hyper::Response<Body>) -> Result<Vec<u8>, DockerError> {
     let mut res = res.into_body();
     let hint = body_size_hint(&res);
 
@@ -235,6 +447,50 @@ async fn read_body_to_vec(res: hyper::Response<Body>) -> eyre::Result<Vec<u8>> {
     Ok(body)
 }
 
+/// Reads a stream of newline-delimited `JsonMessage`s until it ends. A failed pull or build
+/// still responds with 200, so its error is only found in the last message.
+async fn read_progress(
+    res: hyper::Response<Body>,
+    mut on_progress: impl FnMut(&JsonMessage),
+) -> Result<Result<(), String>, DockerError> {
+    let mut body = res.into_body();
+    let mut buffer = Vec::new();
+    while let Some(chunk) = body.next().await {
+        buffer.extend_from_slice(&chunk?);
+        while let Some(end) = buffer.iter().position(|&byte| byte == b'\n') {
+            let line: Vec<u8> = buffer.drain(..=end).collect();
+            if line.iter().all(u8::is_ascii_whitespace) {
+                continue;
+            }
+            let message: JsonMessage = parse_body("JsonMessage", &line)?;
+            if let Some(error) = message.error {
+                return Ok(Err(error));
+            }
+            on_progress(&message);
+        }
+    }
+    Ok(Ok(()))
+}
+
+/// Passes successful responses through, and turns any other into the error in its body.
+async fn error_for_status(res: hyper::Response<Body>) -> Result<hyper::Response<Body>, DockerError> {
+    if res.status().is_success() {
+        return Ok(res);
+    }
+    let status = res.status();
+    let body = read_body_to_vec(res).await?;
+    Err(DockerError::from_response(status, &body))
+}
+
+fn json_body<T: Serialize>(value: &T) -> Result<Body, DockerError> {
+    let body = serde_json::to_vec(value).map_err(DockerError::InvalidBody)?;
+    Ok(Body::from(body))
+}
+
+fn parse_body<T: DeserializeOwned>(what: &'static str, body: &[u8]) -> Result<T, DockerError> {
+    serde_json::from_slice(body).map_err(|source| DockerError::InvalidResponse { what, source })
+}
+
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 #[repr(u8)]
 enum StreamType {
@@ -262,6 +518,20 @@ impl TryFrom<u8> for StreamType {
 }
 
 
+async fn print_raw_stream<R: AsyncRead + Unpin>(mut reader: R) -> eyre::Result<()> {
+    let mut stdout = tokio::io::stdout();
+    let mut buf = [0u8; 4096];
+    loop {
+        let read = reader.read(&mut buf).await?;
+        if read == 0 {
+            return Ok(());
+        }
+        // Flush every chunk so that progress bars and prompts show up immediately
+        stdout.write_all(&buf[..read]).await?;
+        stdout.flush().await?;
+    }
+}
+
 // Reads and prints out the docker encoded stream:
 // https://docs.docker.com/engine/api/v1.41/#operation/ContainerAttach
 //
@@ -286,43 +556,36 @@ impl TryFrom<u8> for StreamType {
 // Extract the frame size from the last four bytes.
 // Read the extracted size and output it on the correct output.
 // Goto 1.
-// #[tracing::instrument(skip(body), err)]
-async fn print_docker_encoded_stream<>(body: Body) -> eyre::Result<()> {
-    let mut body = body;
-    let mut buf = Vec::with_capacity(128);
-    // let mut frame = Vec::with_capacity(64);
-
-    let mut header_is_decoded = false;
-    let mut stream_type = StreamType::Stdout;
-    let mut size = 0;
-
-    while let Some(chunk) = body.next().await {
-        buf.extend_from_slice(&chunk?);
-        // If we have enough bytes for a header, decode the header
-        if !header_is_decoded && buf.len() >= 8 {
-            stream_type = StreamType::try_from(buf[0])?;
-            size = u32::from_be_bytes([buf[4], buf[5], buf[6], buf[7]]) as usize;
-
-            header_is_decoded = true;
-
-            // Eat the header
-            buf.drain(..8);
+// #[tracing::instrument(skip(reader), err)]
+async fn print_docker_encoded_stream<R: AsyncRead + Unpin>(mut reader: R) -> eyre::Result<()> {
+    let mut header = [0u8; 8];
+    let mut frame = Vec::with_capacity(128);
+
+    loop {
+        // The stream ends when the container exits, which must be on a frame boundary
+        match reader.read_exact(&mut header).await {
+            Ok(_) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(()),
+            Err(e) => return Err(e.into()),
         }
 
-        // If we don't have enough bytes for the frame, keep reading
-        if buf.len() < size {
-            continue;
-        }
+        let stream_type = StreamType::try_from(header[0])?;
+        let size = u32::from_be_bytes([header[4], header[5], header[6], header[7]]) as usize;
+
+        frame.resize(size, 0);
+        reader.read_exact(&mut frame).await?;
 
-        // If we have enough bytes for the frame, print it and reset the state
-        if header_is_decoded {
-            header_is_decoded = false;
-            match stream_type {
-                StreamType::Stdout => print!("{}", String::from_utf8_lossy(&buf[..size])),
-                StreamType::Stderr => eprint!("{}", String::from_utf8_lossy(&buf[..size])),
+        match stream_type {
+            StreamType::Stdout => {
+                let mut stdout = std::io::stdout().lock();
+                stdout.write_all(&frame)?;
+                stdout.flush()?;
+            }
+            StreamType::Stderr => {
+                let mut stderr = std::io::stderr().lock();
+                stderr.write_all(&frame)?;
+                stderr.flush()?;
             }
-            buf.drain(..size);
         }
     }
-    Ok(())
-}
\ No newline at end of file
+}
@@ -0,0 +1,13 @@
+use serde::Deserialize;
+
+use crate::dockerapi::container_state::ContainerState;
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub struct ContainerInspect {
+    /// The ID of the container.
+    #[serde(rename = "Id")]
+    pub id: String,
+    /// The state of the container.
+    #[serde(rename = "State")]
+    pub state: ContainerState,
+}
@@ -0,0 +1,14 @@
+use serde::Deserialize;
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub struct ContainerState {
+    /// String representation of the container state, e.g. `running` or `exited`.
+    #[serde(rename = "Status", default)]
+    pub status: String,
+    /// Whether this container has been killed because it ran out of memory.
+    #[serde(rename = "OOMKilled", default)]
+    pub oom_killed: bool,
+    /// The last exit code of this container.
+    #[serde(rename = "ExitCode", default)]
+    pub exit_code: i64,
+}
@@ -0,0 +1,16 @@
+#[derive(Clone, Debug, PartialEq, serde::Deserialize)]
+pub struct ContainerWaitResponse {
+    /// Exit code of the container
+    #[serde(rename = "StatusCode")]
+    pub status_code: i64,
+    /// Set if the daemon failed to wait for the container
+    #[serde(rename = "Error", default)]
+    pub error: Option<ContainerWaitExitError>,
+}
+
+#[derive(Clone, Debug, PartialEq, serde::Deserialize)]
+pub struct ContainerWaitExitError {
+    /// Details of an error
+    #[serde(rename = "Message", default)]
+    pub message: String,
+}
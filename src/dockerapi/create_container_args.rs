@@ -1,5 +1,9 @@
 use std::collections::HashMap;
 
+use crate::dockerapi::mount::Mount;
+use crate::dockerapi::port_binding::PortBinding;
+use crate::dockerapi::ulimit::Ulimit;
+
 #[derive(Debug, Default, Clone, serde::Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct CreateContainerArgs {
@@ -63,10 +67,6 @@ pub struct CreateContainerArgs {
     #[serde(rename = "Volumes")]
     pub volumes: HashMap<String, HashMap<(), ()>>,
 
-    /// A list of volume bindings for this container.
-    #[serde(rename = "Binds")]
-    pub binds: Vec<String>,
-
     /// The working directory for commands to run in.
     #[serde(rename = "WorkingDir")]
     pub working_dir: String,
@@ -83,4 +83,66 @@ pub struct CreateContainerArgs {
     /// `{"<port>/<tcp|udp|sctp>": {}}`
     #[serde(rename = "ExposedPorts")]
     pub exposed_ports: HashMap<String, HashMap<(), ()>>,
+
+    /// Container configuration that depends on the host we are running on.
+    #[serde(rename = "HostConfig")]
+    pub host_config: HostConfig,
+}
+
+#[derive(Debug, Default, Clone, serde::Serialize)]
+pub struct HostConfig {
+    /// A list of string values to customize labels for MLS systems, such as SELinux,
+    /// as well as the seccomp and AppArmor profiles, e.g. `["apparmor=my-profile"]`.
+    #[serde(rename = "SecurityOpt")]
+    pub security_opt: Vec<String>,
+
+    /// A list of kernel capabilities to add to the container.
+    #[serde(rename = "CapAdd")]
+    pub cap_add: Vec<String>,
+
+    /// A list of kernel capabilities to drop from the container, or `["ALL"]`.
+    #[serde(rename = "CapDrop")]
+    pub cap_drop: Vec<String>,
+
+    /// Gives the container full access to the host.
+    #[serde(rename = "Privileged")]
+    pub privileged: bool,
+
+    /// Mount the container's root filesystem as read only.
+    #[serde(rename = "ReadonlyRootfs")]
+    pub readonly_rootfs: bool,
+
+    /// Specification for mounts to be added to the container.
+    #[serde(rename = "Mounts")]
+    pub mounts: Vec<Mount>,
+
+    /// PortMap describes the mapping of container ports to host ports, using the container's
+    /// port-number and protocol as key in the format `<port>/<protocol>`, for example, `80/udp`.
+    #[serde(rename = "PortBindings")]
+    pub port_bindings: HashMap<String, Vec<PortBinding>>,
+
+    /// Memory limit in bytes.
+    #[serde(rename = "Memory")]
+    pub memory: i64,
+
+    /// Total memory limit (memory + swap) in bytes. `0` leaves it to the daemon, `-1` is unlimited.
+    #[serde(rename = "MemorySwap")]
+    pub memory_swap: i64,
+
+    /// CPU quota in units of 10<sup>-9</sup> CPUs.
+    #[serde(rename = "NanoCpus")]
+    pub nano_cpus: i64,
+
+    /// Tune a container's PIDs limit. Set `0` or `-1` for unlimited, or `null` to not change.
+    #[serde(rename = "PidsLimit")]
+    pub pids_limit: Option<i64>,
+
+    /// A list of resource limits to set in the container.
+    #[serde(rename = "Ulimits")]
+    pub ulimits: Vec<Ulimit>,
+
+    /// Storage driver options for this container, e.g. `{"size": "10G"}` to limit the size
+    /// of its writable layer.
+    #[serde(rename = "StorageOpt", skip_serializing_if = "HashMap::is_empty")]
+    pub storage_opt: HashMap<String, String>,
 }
@@ -0,0 +1,11 @@
+use serde::Serialize;
+
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct CreateImageArgs {
+    /// Name of the image to pull, without a tag or digest.
+    #[serde(rename = "fromImage")]
+    pub from_image: String,
+    /// Tag or digest. If empty when pulling an image, all tags for the given image are pulled.
+    #[serde(rename = "tag")]
+    pub tag: String,
+}
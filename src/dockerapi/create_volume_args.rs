@@ -0,0 +1,16 @@
+use std::collections::HashMap;
+
+#[derive(Debug, Default, Clone, serde::Serialize)]
+pub struct CreateVolumeArgs {
+    /// The new volume's name. If not specified, Docker generates a name.
+    #[serde(rename = "Name")]
+    pub name: String,
+
+    /// Name of the volume driver to use.
+    #[serde(rename = "Driver")]
+    pub driver: String,
+
+    /// User-defined key/value metadata.
+    #[serde(rename = "Labels")]
+    pub labels: HashMap<String, String>,
+}
@@ -1 +1,128 @@
+use std::path::PathBuf;
 
+use hyper::StatusCode;
+use serde::Deserialize;
+
+/// An error talking to the docker daemon. Errors reported by the daemon itself are
+/// classified from the status code and message of the response, so that callers can
+/// tell a missing image from a missing container or an outdated daemon.
+#[derive(Debug, thiserror::Error)]
+pub enum DockerError {
+    #[error("Cannot connect to the docker daemon at {}. Is it running? Use --docker-host or DOCKER_HOST to pick another socket", socket.display())]
+    DaemonUnavailable {
+        socket: PathBuf,
+        #[source]
+        source: hyper::Error,
+    },
+
+    #[error("The image `{image}` does not exist. Build or pull it first")]
+    ImageMissing { image: String },
+
+    #[error("Not found: {message}")]
+    NotFound { message: String },
+
+    #[error("Conflict: {message}")]
+    Conflict { message: String },
+
+    #[error("Pulling `{image}` failed: {message}")]
+    PullFailed { image: String, message: String },
+
+    #[error("Building `{image}` failed: {message}")]
+    BuildFailed { image: String, message: String },
+
+    #[error("The docker daemon is too old for cargo-sandbox: {message}")]
+    ApiVersionTooOld { message: String },
+
+    #[error("The docker daemon responded with {status}: {message}")]
+    Api { status: StatusCode, message: String },
+
+    #[error("Request to the docker daemon failed")]
+    Request(#[from] hyper::Error),
+
+    #[error("Invalid request")]
+    InvalidRequest(#[from] hyper::http::Error),
+
+    #[error("Invalid request URI")]
+    InvalidUri(#[from] hyper::http::uri::InvalidUri),
+
+    #[error("Invalid query parameters")]
+    InvalidQuery(#[from] serde_url_params::Error),
+
+    #[error("Invalid request body")]
+    InvalidBody(#[source] serde_json::Error),
+
+    #[error("Invalid {what} from the docker daemon")]
+    InvalidResponse {
+        what: &'static str,
+        #[source]
+        source: serde_json::Error,
+    },
+}
+
+/// The body of every error response of the docker API.
+#[derive(Deserialize)]
+struct ErrorResponse {
+    message: String,
+}
+
+impl DockerError {
+    /// Classifies an error response. Bodies that aren't the usual `{"message": ...}` are
+    /// kept as text.
+    pub(crate) fn from_response(status: StatusCode, body: &[u8]) -> Self {
+        let message = match serde_json::from_slice::<ErrorResponse>(body) {
+            Ok(response) => response.message,
+            Err(_) => String::from_utf8_lossy(body).trim().to_string(),
+        };
+
+        // e.g. `client version 1.43 is too new. Maximum supported API version is 1.41`
+        let lowercase = message.to_lowercase();
+        if lowercase.contains("api version") || lowercase.contains("client version") {
+            return DockerError::ApiVersionTooOld { message };
+        }
+
+        match status {
+            StatusCode::NOT_FOUND => match message.strip_prefix("No such image: ") {
+                Some(image) => DockerError::ImageMissing { image: image.to_string() },
+                None => DockerError::NotFound { message },
+            },
+            StatusCode::CONFLICT => DockerError::Conflict { message },
+            status => DockerError::Api { status, message },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn missing_image_is_told_apart_from_other_404s() {
+        let error = DockerError::from_response(StatusCode::NOT_FOUND, br#"{"message":"No such image: cargo-sandbox-build:0.1.0"}"#);
+        assert!(matches!(error, DockerError::ImageMissing { image } if image == "cargo-sandbox-build:0.1.0"));
+
+        let error = DockerError::from_response(StatusCode::NOT_FOUND, br#"{"message":"No such container: abc"}"#);
+        assert!(matches!(error, DockerError::NotFound { message } if message == "No such container: abc"));
+    }
+
+    #[test]
+    fn conflict() {
+        let body = br#"{"message":"You cannot remove a running container abc. Stop the container before attempting removal or force remove"}"#;
+        assert!(matches!(DockerError::from_response(StatusCode::CONFLICT, body), DockerError::Conflict { .. }));
+    }
+
+    #[test]
+    fn unsupported_api_version() {
+        let body = br#"{"message":"client version 1.41 is too new. Maximum supported API version is 1.40"}"#;
+        let error = DockerError::from_response(StatusCode::BAD_REQUEST, body);
+        assert!(matches!(error, DockerError::ApiVersionTooOld { .. }));
+    }
+
+    #[test]
+    fn non_json_body_is_kept_as_text() {
+        let error = DockerError::from_response(StatusCode::BAD_GATEWAY, b"upstream unavailable\n");
+        assert!(matches!(
+            error,
+            DockerError::Api { status: StatusCode::BAD_GATEWAY, message } if message == "upstream unavailable"
+        ));
+    }
+}
@@ -0,0 +1,14 @@
+use serde::Deserialize;
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub struct ImageInspect {
+    /// ID is the content-addressable ID of an image.
+    #[serde(rename = "Id")]
+    pub id: String,
+    /// List of image names/tags in the local image cache that reference this image.
+    #[serde(rename = "RepoTags", default)]
+    pub repo_tags: Option<Vec<String>>,
+    /// Date and time at which the image was created.
+    #[serde(rename = "Created", default)]
+    pub created: Option<String>,
+}
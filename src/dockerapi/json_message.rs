@@ -0,0 +1,21 @@
+use serde::Deserialize;
+
+/// One line of the progress stream of an image pull or build.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+pub struct JsonMessage {
+    /// Output of the build, including its trailing newline.
+    #[serde(rename = "stream")]
+    pub stream: Option<String>,
+    /// Status of a pull, e.g. `Pulling fs layer`.
+    #[serde(rename = "status")]
+    pub status: Option<String>,
+    /// Progress bar of a download or extraction.
+    #[serde(rename = "progress")]
+    pub progress: Option<String>,
+    /// ID of the layer that `status` is about.
+    #[serde(rename = "id")]
+    pub id: Option<String>,
+    /// Set when the pull or build failed. The stream ends after this message.
+    #[serde(rename = "error")]
+    pub error: Option<String>,
+}
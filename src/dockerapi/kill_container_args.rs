@@ -0,0 +1,8 @@
+use serde::Serialize;
+
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct KillContainerArgs {
+    /// Signal to send to the container as an integer or string (e.g. `SIGINT`).
+    #[serde(rename = "signal")]
+    pub signal: String,
+}
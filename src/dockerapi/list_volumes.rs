@@ -0,0 +1,20 @@
+use serde::{Deserialize, Serialize};
+
+use crate::dockerapi::volume::Volume;
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub struct ListVolumesResponse {
+    /// List of volumes
+    #[serde(rename = "Volumes", default)]
+    pub volumes: Option<Vec<Volume>>,
+    /// Warnings that occurred when fetching the list of volumes.
+    #[serde(rename = "Warnings", default)]
+    pub warnings: Option<Vec<String>>,
+}
+
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct ListVolumesArgs {
+    /// JSON encoded value of the filters to process on the volumes list.
+    #[serde(rename = "filters")]
+    pub filters: Option<String>,
+}
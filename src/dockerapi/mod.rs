@@ -1,16 +1,33 @@
+pub mod archive_args;
+pub mod build_image_args;
 pub mod client;
+pub mod container_inspect;
+pub mod container_state;
 pub mod container_summary;
 pub mod container_summary_host_config;
 pub mod container_summary_network_settings;
+pub mod container_wait_response;
 pub mod create_container_args;
 pub mod create_container_response;
 pub mod create_exec_args;
 pub mod create_exec_response;
+pub mod create_image_args;
+pub mod create_volume_args;
 pub mod endpoint_ipam_config;
 pub mod endpoint_settings;
 pub mod errors;
+pub mod image_inspect;
+pub mod json_message;
+pub mod kill_container_args;
 pub mod list_containers;
+pub mod list_volumes;
+pub mod mount;
 pub mod port;
+pub mod port_binding;
+pub mod resize_args;
 pub mod start_exec_args;
 pub mod start_exec_response;
+pub mod stop_container_args;
+pub mod ulimit;
 pub mod unix_connector;
+pub mod volume;
@@ -0,0 +1,157 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use serde::Serialize;
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Mount {
+    /// Container path.
+    #[serde(rename = "Target")]
+    pub target: String,
+    /// Mount source (e.g. a volume name, a host path).
+    #[serde(rename = "Source")]
+    pub source: String,
+    /// The mount type.
+    #[serde(rename = "Type")]
+    pub _type: MountType,
+    /// Whether the mount should be read-only.
+    #[serde(rename = "ReadOnly")]
+    pub read_only: bool,
+    /// The consistency requirement for the mount.
+    #[serde(rename = "Consistency")]
+    pub consistency: Consistency,
+    /// Optional configuration for the `bind` type.
+    #[serde(rename = "BindOptions", skip_serializing_if = "Option::is_none")]
+    pub bind_options: Option<BindOptions>,
+    /// Optional configuration for the `volume` type.
+    #[serde(rename = "VolumeOptions", skip_serializing_if = "Option::is_none")]
+    pub volume_options: Option<VolumeOptions>,
+    /// Optional configuration for the `tmpfs` type.
+    #[serde(rename = "TmpfsOptions", skip_serializing_if = "Option::is_none")]
+    pub tmpfs_options: Option<TmpfsOptions>,
+}
+
+impl Mount {
+    /// Bind mount a host path into the container.
+    ///
+    /// The Engine API takes paths as strings, so a host path that is not valid UTF-8
+    /// can't be mounted.
+    pub fn bind(source: &Path, target: impl Into<String>) -> eyre::Result<Mount> {
+        let source = match source.to_str() {
+            Some(source) => source.to_string(),
+            None => eyre::bail!(
+                "Can not bind mount {}: the path is not valid UTF-8",
+                source.display()
+            ),
+        };
+        Ok(Mount {
+            target: target.into(),
+            source,
+            _type: MountType::Bind,
+            read_only: false,
+            consistency: Consistency::Default,
+            bind_options: None,
+            volume_options: None,
+            tmpfs_options: None,
+        })
+    }
+
+    /// Mount a named volume into the container, creating it if it does not exist.
+    pub fn volume(name: impl Into<String>, target: impl Into<String>) -> Mount {
+        Mount {
+            target: target.into(),
+            source: name.into(),
+            _type: MountType::Volume,
+            read_only: false,
+            consistency: Consistency::Default,
+            bind_options: None,
+            volume_options: None,
+            tmpfs_options: None,
+        }
+    }
+
+    /// Mount an empty, in-memory filesystem into the container.
+    pub fn tmpfs(target: impl Into<String>) -> Mount {
+        Mount {
+            target: target.into(),
+            source: String::new(),
+            _type: MountType::Tmpfs,
+            read_only: false,
+            consistency: Consistency::Default,
+            bind_options: None,
+            volume_options: None,
+            tmpfs_options: None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash, Serialize)]
+pub enum MountType {
+    /// Mounts a file or directory from the host into the container.
+    #[serde(rename = "bind")]
+    Bind,
+    /// Creates a volume with the given name and options (or uses a pre-existing volume with the same name and options).
+    #[serde(rename = "volume")]
+    Volume,
+    /// Create a tmpfs with the given options.
+    #[serde(rename = "tmpfs")]
+    Tmpfs,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash, Serialize)]
+pub enum Consistency {
+    #[serde(rename = "default")]
+    Default,
+    #[serde(rename = "consistent")]
+    Consistent,
+    #[serde(rename = "cached")]
+    Cached,
+    #[serde(rename = "delegated")]
+    Delegated,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct BindOptions {
+    /// A propagation mode with the value `[r]private`, `[r]shared`, or `[r]slave`.
+    #[serde(rename = "Propagation", skip_serializing_if = "Option::is_none")]
+    pub propagation: Option<Propagation>,
+    /// Disable recursive bind mount.
+    #[serde(rename = "NonRecursive")]
+    pub non_recursive: bool,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash, Serialize)]
+pub enum Propagation {
+    #[serde(rename = "private")]
+    Private,
+    #[serde(rename = "rprivate")]
+    RPrivate,
+    #[serde(rename = "shared")]
+    Shared,
+    #[serde(rename = "rshared")]
+    RShared,
+    #[serde(rename = "slave")]
+    Slave,
+    #[serde(rename = "rslave")]
+    RSlave,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct VolumeOptions {
+    /// Populate volume with data from the target.
+    #[serde(rename = "NoCopy")]
+    pub no_copy: bool,
+    /// User-defined key/value metadata.
+    #[serde(rename = "Labels")]
+    pub labels: HashMap<String, String>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct TmpfsOptions {
+    /// The size for the tmpfs mount in bytes.
+    #[serde(rename = "SizeBytes", skip_serializing_if = "Option::is_none")]
+    pub size_bytes: Option<i64>,
+    /// The permission mode for the tmpfs mount in an integer.
+    #[serde(rename = "Mode", skip_serializing_if = "Option::is_none")]
+    pub mode: Option<i64>,
+}
@@ -34,3 +34,13 @@ pub enum Type {
     #[serde(rename = "sctp")]
     Sctp,
 }
+
+impl Type {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Type::Tcp => "tcp",
+            Type::Udp => "udp",
+            Type::Sctp => "sctp",
+        }
+    }
+}
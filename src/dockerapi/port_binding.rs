@@ -0,0 +1,12 @@
+use serde::Serialize;
+
+/// PortBinding represents a binding between a host IP address and a host port.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct PortBinding {
+    /// Host IP address that the container's port is mapped to.
+    #[serde(rename = "HostIp")]
+    pub host_ip: String,
+    /// Host port number that the container's port is mapped to.
+    #[serde(rename = "HostPort")]
+    pub host_port: String,
+}
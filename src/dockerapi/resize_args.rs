@@ -0,0 +1,11 @@
+use serde::Serialize;
+
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct ResizeArgs {
+    /// Height of the TTY session in characters
+    #[serde(rename = "h")]
+    pub height: u16,
+    /// Width of the TTY session in characters
+    #[serde(rename = "w")]
+    pub width: u16,
+}
@@ -0,0 +1,11 @@
+use serde::Serialize;
+
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct StopContainerArgs {
+    /// Signal to send to the container as an integer or string (e.g. `SIGINT`).
+    #[serde(rename = "signal")]
+    pub signal: Option<String>,
+    /// Number of seconds to wait before killing the container
+    #[serde(rename = "t")]
+    pub timeout: Option<i64>,
+}
@@ -0,0 +1,14 @@
+use serde::Serialize;
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Ulimit {
+    /// Name of the ulimit, e.g. `nofile`.
+    #[serde(rename = "Name")]
+    pub name: String,
+    /// Soft limit.
+    #[serde(rename = "Soft")]
+    pub soft: i64,
+    /// Hard limit.
+    #[serde(rename = "Hard")]
+    pub hard: i64,
+}
@@ -0,0 +1,20 @@
+use serde::Deserialize;
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub struct Volume {
+    /// Name of the volume.
+    #[serde(rename = "Name")]
+    pub name: String,
+    /// Name of the volume driver used by the volume.
+    #[serde(rename = "Driver", default)]
+    pub driver: String,
+    /// Mount path of the volume on the host.
+    #[serde(rename = "Mountpoint", default)]
+    pub mountpoint: String,
+    /// Date/Time the volume was created.
+    #[serde(rename = "CreatedAt")]
+    pub created_at: Option<String>,
+    /// User-defined key/value metadata.
+    #[serde(rename = "Labels")]
+    pub labels: Option<::std::collections::HashMap<String, String>>,
+}
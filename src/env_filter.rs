@@ -0,0 +1,152 @@
+/// Host environment variables that are always passed into the sandbox.
+const DEFAULT_ALLOWED: &[&str] = &["CARGO_BUILD_JOBS", "RUST_RECURSION_COUNT"];
+
+/// Host environment variables that are never passed into the sandbox, no matter what the
+/// configuration says. These are the credentials that a malicious build script would
+/// most like to get its hands on.
+const DENIED: &[&str] = &[
+    "CARGO_REGISTRY_TOKEN",
+    "CARGO_REGISTRIES_*_TOKEN",
+    "CARGO_REGISTRY_GLOBAL_CREDENTIAL_PROVIDERS",
+    "AWS_*",
+    "AZURE_*",
+    "GOOGLE_APPLICATION_CREDENTIALS",
+    "GITHUB_TOKEN",
+    "GH_TOKEN",
+    "GITLAB_TOKEN",
+    "CI_JOB_TOKEN",
+    "NPM_TOKEN",
+    "SSH_AUTH_SOCK",
+    "*_TOKEN",
+    "*_SECRET",
+    "*_SECRET_*",
+    "*PASSWORD*",
+    "*_API_KEY",
+];
+
+/// Host environment variables that describe where things are on the host. In the sandbox
+/// they would send cargo past the cache volumes, or into the read-only root filesystem, so
+/// they are never passed either, even though they hold no secret.
+const HOST_LAYOUT: &[&str] = &[
+    "CARGO_HOME",
+    "CARGO_TARGET_DIR",
+    "CARGO_BUILD_TARGET_DIR",
+    "RUSTUP_HOME",
+    "RUSTUP_TOOLCHAIN",
+    "HOME",
+    "PATH",
+];
+
+/// The `KEY=value` pairs from the host environment to set in the sandbox.
+///
+/// `extra` holds names and `*` glob patterns from the configuration, such as `CARGO_*`
+/// or `RUSTFLAGS`, in addition to the defaults. Anything matching the built-in denylist,
+/// and any host path in `HOST_LAYOUT`, is dropped regardless.
+pub fn get_env(extra: &[String]) -> Vec<String> {
+    for key in extra.iter().filter(|key| !key.contains('*') && std::env::var_os(key).is_some()) {
+        if is_denied(key) {
+            eprintln!("Not passing {key} into the sandbox: it may hold a credential");
+        } else if HOST_LAYOUT.contains(&key.as_str()) {
+            eprintln!("Not passing {key} into the sandbox: it is a path on the host");
+        }
+    }
+
+    std::env::vars()
+        .filter_map(|(key, value)| {
+            if should_pass_key(&key, extra) {
+                Some(format!("{}={}", key, value))
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+pub fn should_pass_key(key: &str, extra: &[String]) -> bool {
+    if is_denied(key) || HOST_LAYOUT.contains(&key) {
+        return false;
+    }
+    DEFAULT_ALLOWED.contains(&key) || extra.iter().any(|pattern| matches(pattern, key))
+}
+
+fn is_denied(key: &str) -> bool {
+    DENIED.iter().any(|pattern| matches(pattern, key))
+}
+
+/// Matches `key` against `pattern`, where `*` matches any run of characters.
+pub(crate) fn matches(pattern: &str, key: &str) -> bool {
+    let mut parts = pattern.split('*');
+    // There is always at least one part, and the first must be a prefix
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = key.strip_prefix(first) else {
+        return false;
+    };
+
+    let parts: Vec<&str> = parts.collect();
+    let Some((last, middle)) = parts.split_last() else {
+        // No `*` at all, so this is an exact match
+        return rest.is_empty();
+    };
+
+    for part in middle {
+        match rest.find(part) {
+            Some(index) => rest = &rest[index + part.len()..],
+            None => return false,
+        }
+    }
+    rest.ends_with(last)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exact_names_match_only_themselves() {
+        assert!(matches("RUSTFLAGS", "RUSTFLAGS"));
+        assert!(!matches("RUSTFLAGS", "RUSTFLAGS_EXTRA"));
+        assert!(!matches("RUSTFLAGS", "CARGO_ENCODED_RUSTFLAGS"));
+    }
+
+    #[test]
+    fn prefix_patterns() {
+        assert!(matches("CARGO_*", "CARGO_PROFILE_RELEASE_LTO"));
+        assert!(matches("CARGO_*", "CARGO_"));
+        assert!(!matches("CARGO_*", "XCARGO_HOME"));
+    }
+
+    #[test]
+    fn suffix_patterns() {
+        assert!(matches("*_TOKEN", "GITHUB_TOKEN"));
+        assert!(!matches("*_TOKEN", "GITHUB_TOKENS"));
+        assert!(!matches("*_TOKEN", "TOKEN"));
+    }
+
+    #[test]
+    fn middle_patterns() {
+        assert!(matches("CARGO_REGISTRIES_*_TOKEN", "CARGO_REGISTRIES_MY_REGISTRY_TOKEN"));
+        assert!(!matches("CARGO_REGISTRIES_*_TOKEN", "CARGO_REGISTRIES_MY_REGISTRY_INDEX"));
+        assert!(matches("*PASSWORD*", "DB_PASSWORD_FILE"));
+        // The prefix and suffix may not overlap
+        assert!(!matches("AB*BC", "ABC"));
+    }
+
+    #[test]
+    fn denylist_wins_over_pass() {
+        let extra = vec!["CARGO_*".to_string(), "CARGO_REGISTRIES_FOO_TOKEN".to_string()];
+        assert!(!should_pass_key("CARGO_REGISTRIES_FOO_TOKEN", &extra));
+        assert!(!should_pass_key("CARGO_REGISTRY_TOKEN", &extra));
+        assert!(!should_pass_key("AWS_SECRET_ACCESS_KEY", &["AWS_SECRET_ACCESS_KEY".to_string()]));
+        assert!(should_pass_key("CARGO_BUILD_JOBS", &[]));
+    }
+
+    #[test]
+    fn host_paths_are_never_passed() {
+        let extra = vec!["CARGO_*".to_string(), "RUSTUP_*".to_string()];
+        assert!(should_pass_key("CARGO_PROFILE_RELEASE_LTO", &extra));
+        for key in ["CARGO_HOME", "CARGO_TARGET_DIR", "CARGO_BUILD_TARGET_DIR", "RUSTUP_HOME"] {
+            assert!(!should_pass_key(key, &extra), "{key} is not passed");
+        }
+        assert!(!should_pass_key("PATH", &["*".to_string()]));
+    }
+}
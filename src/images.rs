@@ -0,0 +1,160 @@
+use std::collections::HashMap;
+use std::io::Write;
+
+use maplit::hashmap;
+
+use crate::config::Config;
+use crate::container_type::ContainerType;
+use crate::dockerapi::build_image_args::BuildImageArgs;
+use crate::dockerapi::client::Client;
+use crate::dockerapi::create_image_args::CreateImageArgs;
+use crate::dockerapi::errors::DockerError;
+use crate::dockerapi::json_message::JsonMessage;
+use crate::toolchain::Toolchain;
+
+const VERSION_LABEL: &str = "cargo-sandbox.version";
+
+/// The image to run a container from, and how to get it if it doesn't exist locally.
+pub struct SandboxImage {
+    pub name: String,
+    /// Build args for our own Dockerfile, or `None` for an image that has to be pulled.
+    build_args: Option<HashMap<&'static str, String>>,
+}
+
+/// Picks the image for `container_type`. A configured image is used as is, otherwise the
+/// Build image is based on the `rust` image of the project's toolchain, if that is a plain
+/// version. Other toolchains are installed into a volume, see `toolchain::toolchain_mount`.
+pub fn select_image(config: &Config, container_type: ContainerType, toolchain: Option<&Toolchain>) -> SandboxImage {
+    let name = config.image(container_type);
+    if name != container_type.default_image() {
+        return SandboxImage { name, build_args: None };
+    }
+
+    let rust_version = toolchain.and_then(Toolchain::rust_image_version);
+    match (container_type, rust_version) {
+        (ContainerType::Build, Some(rust_version)) => SandboxImage {
+            name: format!("{name}-rust-{rust_version}"),
+            build_args: Some(hashmap! { "RUST_VERSION" => rust_version.to_string() }),
+        },
+        _ => SandboxImage {
+            name,
+            build_args: Some(HashMap::new()),
+        },
+    }
+}
+
+/// Makes sure that `image` exists locally. Our own images are built from the Dockerfiles
+/// embedded in the binary, any other image is pulled.
+pub async fn ensure_image(client: &Client, container_type: ContainerType, image: &SandboxImage) -> eyre::Result<()> {
+    let name = &image.name;
+    match client.inspect_image(name).await {
+        Ok(_) => return Ok(()),
+        Err(DockerError::ImageMissing { .. }) => {}
+        Err(e) => return Err(e.into()),
+    }
+
+    match &image.build_args {
+        Some(build_args) => {
+            eprintln!("Building {name}, this only happens once per version of cargo-sandbox");
+            build_image(client, container_type, name, build_args).await?;
+        }
+        None => {
+            eprintln!("Pulling {name}");
+            let (from_image, tag) = split_reference(name);
+            let args = CreateImageArgs {
+                from_image: from_image.to_string(),
+                tag: tag.to_string(),
+            };
+            client.pull_image(args, print_pull_progress).await?;
+        }
+    }
+    Ok(())
+}
+
+async fn build_image(
+    client: &Client,
+    container_type: ContainerType,
+    image: &str,
+    build_args: &HashMap<&'static str, String>,
+) -> eyre::Result<()> {
+    let dockerfile = container_type.dockerfile().as_bytes();
+    let mut header = tar::Header::new_gnu();
+    header.set_size(dockerfile.len() as u64);
+    header.set_mode(0o644);
+    let mut context = tar::Builder::new(Vec::new());
+    context.append_data(&mut header, "Dockerfile", dockerfile)?;
+
+    let args = BuildImageArgs {
+        dockerfile: "Dockerfile".to_string(),
+        tag: image.to_string(),
+        build_args: (!build_args.is_empty()).then(|| serde_json::to_string(build_args)).transpose()?,
+        labels: Some(serde_json::to_string(&hashmap! {
+            VERSION_LABEL => env!("CARGO_PKG_VERSION"),
+        })?),
+        force_rm: true,
+    };
+    client.build_image(args, context.into_inner()?, print_build_progress).await?;
+    Ok(())
+}
+
+/// Splits `name[:tag]` or `name@digest` into the name and tag or digest, which the pull
+/// endpoint takes separately.
+fn split_reference(image: &str) -> (&str, &str) {
+    if let Some((name, digest)) = image.split_once('@') {
+        return (name, digest);
+    }
+    // A colon before the last slash separates a registry's port, not a tag
+    let name_start = image.rfind('/').map_or(0, |slash| slash + 1);
+    match image[name_start..].rfind(':') {
+        Some(colon) => (&image[..name_start + colon], &image[name_start + colon + 1..]),
+        None => (image, "latest"),
+    }
+}
+
+fn print_build_progress(message: &JsonMessage) {
+    if let Some(stream) = &message.stream {
+        let mut stderr = std::io::stderr().lock();
+        let _ = stderr.write_all(stream.as_bytes());
+        let _ = stderr.flush();
+    }
+}
+
+/// Prints status changes, but not every update of the progress bars.
+fn print_pull_progress(message: &JsonMessage) {
+    if message.progress.is_some() {
+        return;
+    }
+    match (&message.id, &message.status) {
+        (Some(id), Some(status)) => eprintln!("{id}: {status}"),
+        (None, Some(status)) => eprintln!("{status}"),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tags_and_default_tag() {
+        assert_eq!(split_reference("cargo-sandbox-build:0.1.0"), ("cargo-sandbox-build", "0.1.0"));
+        assert_eq!(split_reference("rust"), ("rust", "latest"));
+    }
+
+    #[test]
+    fn registry_port_is_not_a_tag() {
+        assert_eq!(
+            split_reference("registry.example.com:5000/cargo-sandbox-build"),
+            ("registry.example.com:5000/cargo-sandbox-build", "latest"),
+        );
+        assert_eq!(
+            split_reference("registry.example.com:5000/team/cargo-sandbox-build:0.1.0"),
+            ("registry.example.com:5000/team/cargo-sandbox-build", "0.1.0"),
+        );
+    }
+
+    #[test]
+    fn digests() {
+        assert_eq!(split_reference("rust@sha256:0123abcd"), ("rust", "sha256:0123abcd"));
+    }
+}
@@ -1,23 +1,88 @@
 #![allow(dead_code, unused)]
+use std::collections::HashMap;
+use std::path::{Component, Path, PathBuf};
+use std::time::Duration;
+
+use eyre::WrapErr;
+
 use maplit::hashmap;
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::time::Instant;
 
 use container_type::ContainerType;
 use dockerapi::client::Client;
 
+use crate::cache::DependencyCache;
+use crate::config::Config;
+use crate::credentials::{Credentials, PublishCredentials};
+use crate::dockerapi::archive_args::ArchiveArgs;
 use crate::dockerapi::container_summary::ContainerSummary;
-use crate::dockerapi::create_container_args::CreateContainerArgs;
+use crate::dockerapi::create_container_args::{CreateContainerArgs, HostConfig};
 use crate::dockerapi::create_exec_args::CreateExecArgs;
+use crate::dockerapi::errors::DockerError;
+use crate::dockerapi::kill_container_args::KillContainerArgs;
+use crate::dockerapi::mount::{Consistency, Mount};
+use crate::dockerapi::port_binding::PortBinding;
+use crate::dockerapi::resize_args::ResizeArgs;
+use crate::dockerapi::stop_container_args::StopContainerArgs;
+use crate::dockerapi::ulimit::Ulimit;
+use crate::project::{Project, PROJECT_ID_LABEL, PROJECT_NAME_LABEL};
+use crate::publish::{PackagedCrate, Registry, DOCKER_PACKAGE_DIR, DOCKER_UPLOAD_DIR};
+use crate::sandbox_args::{PublishedPort, SandboxArgs};
+use crate::toolchain::Toolchain;
 
+mod cache;
 mod config;
 mod container;
 mod container_type;
+mod credentials;
+mod docker_host;
 mod dockerapi;
+mod env_filter;
+mod images;
+mod path_dependencies;
+mod project;
+mod publish;
+mod sandbox_args;
+mod signals;
+mod terminal;
+mod toolchain;
 
-const DOCKER_USER: &'static str = "cargo-sandbox-user";
+const DOCKER_USER: &str = "cargo-sandbox-user";
+/// How long a container has to exit after a forwarded signal before it is stopped.
+const SIGNAL_GRACE_PERIOD: Duration = Duration::from_secs(10);
+/// The exit code when a command ran into `limits.timeout`, the same as `timeout(1)`'s.
+const TIMEOUT_EXIT_CODE: i32 = 124;
+/// Where the target volume is mounted when the project is mounted read-only.
+const DOCKER_TARGET_DIR: &str = "/home/cargo-sandbox-user/target";
+
+/// How a single sandboxed command should be run.
+#[derive(Clone, Debug, Default)]
+struct ExecOptions {
+    /// Run the container without any network access.
+    network_disabled: bool,
+    /// Keep the container's stdin open so that input can be forwarded to it.
+    open_stdin: bool,
+    /// Container ports to publish to the host.
+    ports: Vec<PublishedPort>,
+    /// Mounts in addition to the project directory.
+    mounts: Vec<Mount>,
+    /// Mount the project read-only, with `target` in a volume instead.
+    read_only_project: bool,
+    /// Allocate a TTY for the container.
+    tty: bool,
+    /// Tar archives to extract into the container before it starts, by destination directory.
+    archives: Vec<(String, Vec<u8>)>,
+    /// Environment variables that cargo-sandbox itself sets, as `NAME=value`.
+    env: Vec<String>,
+    /// When the whole command runs into `limits.timeout`, shared by all of its containers.
+    /// Set by `command_deadline`, a container without one gets the full timeout.
+    deadline: Option<Instant>,
+}
 
 async fn find_container(
     client: &Client,
-    project_name: &str,
+    project: &Project,
     container_type: ContainerType,
 ) -> eyre::Result<Option<ContainerSummary>> {
     let list_containers_response = client
@@ -27,7 +92,7 @@ async fn find_container(
             filters: Some(
                 serde_json::json!({
                     "label": [
-                        format!("cargo-sandbox.project-name={}", project_name),
+                        format!("{}={}", PROJECT_ID_LABEL, project.id),
                         format!("cargo-sandbox.container-type={}", container_type.as_str()),
                     ]
                 })
@@ -42,14 +107,19 @@ async fn find_container(
 
 async fn find_and_remove_container(
     client: &Client,
-    project_name: &str,
+    project: &Project,
     container_type: ContainerType,
 ) -> eyre::Result<()> {
-    let container = find_container(client, project_name, container_type).await?;
+    let container = find_container(client, project, container_type).await?;
     if let Some(container) = container {
-        client
-            .remove_container(container.id, false, true)
-            .await?;
+        match client.remove_container(container.id, false, true).await {
+            Ok(()) => {}
+            Err(DockerError::Conflict { message }) => eyre::bail!(
+                "A {} container for this project is still running, is another cargo-sandbox using it? ({message})",
+                container_type.as_str(),
+            ),
+            Err(e) => return Err(e.into()),
+        }
     }
 
     Ok(())
@@ -57,61 +127,264 @@ async fn find_and_remove_container(
 
 async fn create_container(
     client: &Client,
-    project_name: &str,
+    project: &Project,
+    config: &Config,
     container_type: ContainerType,
     command: Vec<String>,
-    network_disabled: bool,
+    options: &ExecOptions,
 ) -> eyre::Result<ContainerSummary> {
-    // let binds: Vec<String> = vec![];
-    let binds = vec![format!(
-        "{}/:/home/{DOCKER_USER}/{}:cached",
-        std::env::current_dir()?.to_str().unwrap(),
-        project_name
-    )];
+    let container_root = container_root(project);
+    let mut mounts = host_mounts(project, config, container_type, options.read_only_project)?;
+    mounts.extend(options.mounts.iter().cloned());
+
+    let mut env = passed_env(config, container_type);
+    env.extend(options.env.iter().cloned());
+    if options.read_only_project {
+        let target_volume = cache::target_volume(client, project).await?;
+        mounts.push(Mount::volume(target_volume, DOCKER_TARGET_DIR));
+        env.push(format!("CARGO_TARGET_DIR={DOCKER_TARGET_DIR}"));
+    }
 
     let user = DOCKER_USER.into();
 
-    client
+    let mut exposed_ports = HashMap::new();
+    let mut port_bindings: HashMap<String, Vec<PortBinding>> = HashMap::new();
+    for port in &options.ports {
+        exposed_ports.insert(port.container_key(), HashMap::new());
+        port_bindings
+            .entry(port.container_key())
+            .or_default()
+            .push(PortBinding {
+                host_ip: port.host_ip.clone(),
+                // An empty host port lets the daemon pick one
+                host_port: port.host_port.map(|host_port| host_port.to_string()).unwrap_or_default(),
+            });
+    }
+
+    let toolchain = match container_type {
+        ContainerType::Build => Toolchain::detect(project)?,
+        ContainerType::Publish => None,
+    };
+    let image = images::select_image(config, container_type, toolchain.as_ref());
+    images::ensure_image(client, container_type, &image).await?;
+    if let Some(toolchain) = toolchain.filter(|toolchain| toolchain.rust_image_version().is_none()) {
+        mounts.push(toolchain::toolchain_mount(client, &image.name, &toolchain).await?);
+        env.push(format!("RUSTUP_HOME={}", toolchain::DOCKER_RUSTUP_HOME));
+        env.push(format!("RUSTUP_TOOLCHAIN={}", toolchain.channel));
+    }
+
+    let working_dir = match container_type {
+        ContainerType::Build => Path::new(&container_root).join(&project.relative_dir).to_string_lossy().into_owned(),
+        ContainerType::Publish => format!("/home/{DOCKER_USER}"),
+    };
+
+    // Every capability is dropped, the project's configuration may only give some back
+    // to the Build container
+    let (cap_add, read_only_rootfs, no_new_privileges) = match container_type {
+        ContainerType::Build => (config.security.cap_add.clone(), config.read_only_rootfs(), config.no_new_privileges()),
+        ContainerType::Publish => (Vec::new(), true, true),
+    };
+    if read_only_rootfs {
+        mounts.extend(container_type.tmpfs_dirs().iter().map(|dir| Mount::tmpfs(*dir)));
+    }
+
+    let created = client
         .create_container(CreateContainerArgs {
             cmd: command,
             // entrypoint: command.join(" "),
-            image: format!("cargo-sandbox-{}", container_type.as_str()),
+            image: image.name,
             labels: hashmap! {
                 "cargo-sandbox.version".into() => env!("CARGO_PKG_VERSION").to_string(),
-                "cargo-sandbox.project-name".into() => project_name.into(),
+                PROJECT_ID_LABEL.into() => project.id.clone(),
+                PROJECT_NAME_LABEL.into() => project.name.clone(),
                 "cargo-sandbox.container-type".into() => container_type.as_str().into(),
             },
-            working_dir: format!("/home/{DOCKER_USER}/{project_name}"),
-            binds,
+            working_dir,
             user,
-            tty: false,
-            network_disabled: Some(network_disabled),
+            env,
+            tty: options.tty,
+            network_disabled: Some(options.network_disabled),
             attach_stdout: true,
             attach_stderr: true,
             attach_stdin: true,
+            open_stdin: options.open_stdin,
+            stdin_once: options.open_stdin,
+            exposed_ports,
+            host_config: HostConfig {
+                security_opt: container_type.security_opts(no_new_privileges),
+                cap_add,
+                cap_drop: vec!["ALL".to_string()],
+                privileged: false,
+                readonly_rootfs: read_only_rootfs,
+                mounts,
+                port_bindings,
+                memory: config.limits.memory.map_or(0, |memory| memory.0),
+                memory_swap: config.memory_swap().unwrap_or(0),
+                nano_cpus: config.limits.cpus.map_or(0, |cpus| (cpus.0 * 1e9) as i64),
+                pids_limit: Some(config.pids_limit()),
+                ulimits: config
+                    .limits
+                    .ulimits
+                    .iter()
+                    .map(|(name, &limit)| Ulimit {
+                        name: name.clone(),
+                        soft: limit,
+                        hard: limit,
+                    })
+                    .collect(),
+                storage_opt: config
+                    .limits
+                    .storage
+                    .map(|storage| hashmap! { "size".to_string() => storage.0.to_string() })
+                    .unwrap_or_default(),
+            },
             ..Default::default()
         })
-        .await?;
+        .await
+        .map_err(|e| explain_create_error(e, container_type))?;
 
-    let container = find_container(client, project_name, container_type).await?;
-    if let Some(container) = container {
-        return Ok(container);
+    find_container(client, project, container_type)
+        .await?
+        .ok_or_else(|| eyre::eyre!("Container {} was removed right after it was created", created.id))
+}
+
+/// The host environment variables that a container gets. The Publish container holds the
+/// registry token, so nothing from the host environment is passed into it.
+fn passed_env(config: &Config, container_type: ContainerType) -> Vec<String> {
+    match container_type {
+        ContainerType::Build => env_filter::get_env(&config.env.pass),
+        ContainerType::Publish => Vec::new(),
     }
+}
 
-    panic!("This should not be possible")
+/// Where the workspace root is mounted in every container.
+fn container_root(project: &Project) -> String {
+    format!("/home/{DOCKER_USER}/{}", project.name)
 }
 
-async fn start_container(client: &Client, container: &ContainerSummary) -> eyre::Result<()> {
+/// The host directories that a container can reach. The Build container gets the workspace,
+/// path dependencies outside of it, which are always read-only, and the configured mounts.
+/// The Publish container only ever gets the packaged `.crate`, so it gets none.
+fn host_mounts(
+    project: &Project,
+    config: &Config,
+    container_type: ContainerType,
+    read_only_project: bool,
+) -> eyre::Result<Vec<Mount>> {
+    if container_type == ContainerType::Publish {
+        return Ok(Vec::new());
+    }
+
+    let container_root = container_root(project);
+    let mut mounts = vec![Mount {
+        consistency: Consistency::Cached,
+        read_only: read_only_project,
+        ..Mount::bind(&project.root, &container_root)?
+    }];
+
+    // The sandbox's own policy is read from these, so they stay read-only in a writable project
+    if !read_only_project {
+        for file in existing_policy_files(project, true) {
+            let target = Path::new(&container_root).join(file.strip_prefix(&project.root)?);
+            mounts.push(Mount {
+                read_only: true,
+                ..Mount::bind(&file, target.to_string_lossy())?
+            });
+        }
+    }
+
+    for external in path_dependencies::external_paths(project, &container_root)? {
+        mounts.push(Mount {
+            read_only: true,
+            ..Mount::bind(&external.host_path, external.container_path)?
+        });
+    }
+
+    for mount in &config.mounts {
+        mounts.push(Mount {
+            read_only: mount.read_only,
+            ..Mount::bind(&mount.source, &mount.target)?
+        });
+    }
+    Ok(mounts)
+}
+
+/// The files that sandbox policy is read from that exist, or with `exist` unset, those that don't.
+fn existing_policy_files(project: &Project, exist: bool) -> Vec<PathBuf> {
+    config::policy_files(&project.root, &project.root.join(&project.relative_dir))
+        .into_iter()
+        .filter(|file| file.symlink_metadata().is_ok() == exist)
+        .collect()
+}
+
+/// A policy file that didn't exist can't be mounted read-only, so if sandboxed code created
+/// one it is removed again before anything reads it.
+fn remove_planted_policy_files(absent: &[PathBuf]) -> eyre::Result<()> {
+    let planted: Vec<&PathBuf> = absent.iter().filter(|file| file.symlink_metadata().is_ok()).collect();
+    for file in &planted {
+        if file.is_dir() {
+            std::fs::remove_dir_all(file)?;
+        } else {
+            std::fs::remove_file(file)?;
+        }
+    }
+    if !planted.is_empty() {
+        let planted: Vec<String> = planted.iter().map(|file| file.display().to_string()).collect();
+        eyre::bail!(
+            "The sandboxed command created {}, which would change the sandbox's policy, so it was removed",
+            planted.join(", "),
+        );
+    }
+    Ok(())
+}
+
+/// The daemon only tells us that it failed to apply a profile or a limit, so we add the
+/// context needed to fix it. AppArmor profiles in particular must be loaded by the user.
+fn explain_create_error(error: DockerError, container_type: ContainerType) -> eyre::Report {
+    let message = error.to_string().to_lowercase();
+    let error = eyre::Report::new(error);
+    if message.contains("storage-opt") || message.contains("storage opt") {
+        return error.wrap_err(
+            "The docker daemon's storage driver can't limit the size of a container, remove `limits.storage` or `--storage-size`",
+        );
+    }
+    if message.contains("apparmor") {
+        if let Some((name, source)) = container_type.apparmor_profile() {
+            let path = std::env::temp_dir().join(format!("{name}.aa-profile"));
+            if let Err(e) = std::fs::write(&path, source) {
+                return error.wrap_err(format!(
+                    "The docker daemon rejected the AppArmor profile `{name}`, and writing it to {} failed: {e}",
+                    path.display(),
+                ));
+            }
+            return error.wrap_err(format!(
+                "The docker daemon rejected the AppArmor profile `{name}`. It must be loaded before use: `sudo apparmor_parser -r -W {}`",
+                path.display(),
+            ));
+        }
+    }
+    if message.contains("seccomp") {
+        return error.wrap_err(format!(
+            "The docker daemon rejected the seccomp profile for the {} container. Is the daemon built with seccomp support?",
+            container_type.as_str(),
+        ));
+    }
+    error
+}
+
+async fn start_container(client: &Client, container: &ContainerSummary, container_type: ContainerType) -> eyre::Result<()> {
     match container.state.as_str() {
-        state @ ("created" | "dead" | "exited" | "paused") => {
-            println!("Container is {state} - starting it");
-            client.start_container(&container.id).await?;
+        "created" | "dead" | "exited" | "paused" => {
+            client
+                .start_container(&container.id)
+                .await
+                .map_err(|e| explain_create_error(e, container_type))?;
         }
         state @ "running" => {
-            println!("Container is already running: {state}");
+            eprintln!("Container is already running: {state}");
         }
         other => {
-            println!("Container is in an unknown state: {other}");
+            eprintln!("Container is in an unknown state: {other}");
         }
     }
     Ok(())
@@ -132,147 +405,834 @@ fn make_cargo_cmd(
     }
 }
 
-async fn ephemeral_exec(client: &Client, project_name: &str, cargo_command: Vec<String>, container_type: ContainerType) -> eyre::Result<()> {
+/// Runs `cargo_command` in a fresh container and returns the exit code of the command.
+async fn ephemeral_exec(
+    client: &Client,
+    project: &Project,
+    config: &Config,
+    cargo_command: Vec<String>,
+    container_type: ContainerType,
+    options: &ExecOptions,
+) -> eyre::Result<i32> {
+
+    let options = &ExecOptions {
+        tty: terminal::stdout_is_terminal(),
+        ..options.clone()
+    };
 
     // First we should remove the container if it exists
-    find_and_remove_container(client, project_name, container_type).await?;
+    find_and_remove_container(client, project, container_type).await?;
+
+    let absent_policy_files = if container_type == ContainerType::Build && !options.read_only_project {
+        existing_policy_files(project, false)
+    } else {
+        Vec::new()
+    };
 
     let build_container =
         create_container(
             client,
-            project_name,
+            project,
+            config,
             container_type,
             cargo_command,
-            false
+            options,
         ).await?;
 
-    let attach_client = client.clone();
-    let container_id = build_container.id.clone();
-    let attach = tokio::spawn(async move {
-        println!("attaching");
-        attach_client.attach(&container_id).await?;
-        println!("attached");
-        Ok::<(), eyre::Error>(())
-    });
+    // However running it ends, the container must not be left behind, let alone running
+    let result = run_container(client, project, config, &build_container, container_type, options).await;
+    if result.is_err() {
+        let stop_args = StopContainerArgs {
+            timeout: Some(0),
+            ..Default::default()
+        };
+        if let Err(e) = client.stop_container(&build_container.id, stop_args).await {
+            eprintln!("Failed to stop the container: {e}");
+        }
+    }
+    let removed = client.remove_container(build_container.id, true, true).await;
+    let planted = remove_planted_policy_files(&absent_policy_files);
 
-    println!("starting");
-    start_container(client, &build_container).await?;
-    println!("started");
+    let exit_code = match (result, removed) {
+        (Ok(exit_code), Ok(())) => exit_code,
+        (Ok(_), Err(e)) => return Err(e.into()),
+        (Err(e), removed) => {
+            if let Err(remove_error) = removed {
+                eprintln!("Failed to remove the container: {remove_error}");
+            }
+            if let Err(planted) = planted {
+                eprintln!("{planted}");
+            }
+            return Err(e);
+        }
+    };
+    planted?;
+    Ok(exit_code)
+}
 
-    attach.await?;
+/// Starts the created `container` with its output attached, forwards signals to it and waits
+/// for it to exit. The container is removed by the caller, whether this succeeds or not.
+async fn run_container(
+    client: &Client,
+    project: &Project,
+    config: &Config,
+    container: &ContainerSummary,
+    container_type: ContainerType,
+    options: &ExecOptions,
+) -> eyre::Result<i32> {
+    for (path, archive) in &options.archives {
+        client
+            .put_archive(&container.id, ArchiveArgs { path: path.clone() }, archive.clone())
+            .await?;
+    }
 
-    client.remove_container(build_container.id, true, true).await?;
+    // Attach before starting so that none of the output is missed
+    let attached = client.attach(&container.id).await?;
+    let mut attach = tokio::spawn(attached.forward(options.open_stdin, options.tty));
 
-    Ok(())
+    start_container(client, container, container_type).await?;
+    // Signals are forwarded to the container rather than killing us and leaving it running
+    let mut signals = signals::Forwarding::start()?;
+
+    if !options.ports.is_empty() {
+        print_published_ports(client, project, container_type).await?;
+    }
+
+    // Keystrokes, including Ctrl-C, should go to the container's TTY as they are typed
+    let raw_mode = if options.tty && options.open_stdin && terminal::stdin_is_terminal() {
+        Some(terminal::RawModeGuard::enable()?)
+    } else {
+        None
+    };
+
+    let resize = options
+        .tty
+        .then(|| tokio::spawn(forward_window_size(client.clone(), container.id.clone())));
+
+    let deadline = options.deadline.or_else(|| command_deadline(config));
+    let deadline = async {
+        match deadline {
+            Some(deadline) => tokio::time::sleep_until(deadline).await,
+            None => std::future::pending().await,
+        }
+    };
+
+    let waited = async {
+        let mut timed_out = false;
+        let received = tokio::select! {
+            result = &mut attach => {
+                result
+                    .map_err(eyre::Report::from)
+                    .and_then(|result| result)
+                    .wrap_err("Lost the connection to the container's output")?;
+                None
+            }
+            received = signals.recv() => Some(received),
+            _ = deadline => {
+                timed_out = true;
+                None
+            }
+        };
+
+        if timed_out {
+            // A hung proc macro or build script won't react to anything gentler
+            let kill_args = KillContainerArgs {
+                signal: "SIGKILL".to_string(),
+            };
+            client.kill_container(&container.id, kill_args).await?;
+        }
+
+        if let Some(received) = received {
+            eprintln!("Received {received}, forwarding it to the container");
+            let kill_args = KillContainerArgs {
+                signal: received.to_string(),
+            };
+            if let Err(e) = client.kill_container(&container.id, kill_args).await {
+                eprintln!("Failed to forward {received}: {e}");
+            }
+
+            // A second signal skips the grace period
+            let exited = tokio::select! {
+                result = tokio::time::timeout(SIGNAL_GRACE_PERIOD, &mut attach) => result.is_ok(),
+                _ = signals.recv() => false,
+            };
+            if !exited {
+                eprintln!("Stopping the container");
+                let stop_args = StopContainerArgs {
+                    timeout: Some(0),
+                    ..Default::default()
+                };
+                client.stop_container(&container.id, stop_args).await?;
+            }
+        }
+        Ok::<_, eyre::Report>(timed_out)
+    }
+    .await;
+
+    attach.abort();
+    if let Some(resize) = resize {
+        resize.abort();
+    }
+    drop(raw_mode);
+    let timed_out = waited?;
+
+    let exit = client.wait(container.id.clone()).await?;
+    if let Some(error) = &exit.error {
+        if !error.message.is_empty() {
+            eprintln!("Container exited with an error: {}", error.message);
+        }
+    }
+    // Otherwise a build that ran out of memory only shows up as a process killed by SIGKILL
+    if client.inspect_container(&container.id).await?.state.oom_killed {
+        let limit = config.limits.memory.map_or_else(|| "the host's memory".to_string(), |memory| format!("{} bytes", memory.0));
+        eprintln!(
+            "The {} container ran out of memory ({limit}) and was killed. Raise `limits.memory` or pass `--memory`",
+            container_type.as_str(),
+        );
+    }
+
+    if let (true, Some(timeout)) = (timed_out, config.limits.timeout) {
+        eprintln!(
+            "The {} container did not finish within the timeout of {timeout} and was killed",
+            container_type.as_str(),
+        );
+        return Ok(TIMEOUT_EXIT_CODE);
+    }
+    Ok(exit_code(exit.status_code))
 }
 
-async fn cargo_build(client: &Client, project_name: &str, args: Vec<String>) -> eyre::Result<()> {
-    let cargo_cmd = make_cargo_cmd(false, args);
-    ephemeral_exec(client, project_name, cargo_cmd, ContainerType::Build).await?;
+/// When a command that starts now runs into `limits.timeout`, if there is one. Commands
+/// that run several containers pass this to each of them, so that together they never
+/// take longer than the timeout.
+fn command_deadline(config: &Config) -> Option<Instant> {
+    config.limits.timeout.map(|timeout| Instant::now() + timeout.0)
+}
 
+/// Keeps the size of the container's TTY in sync with our terminal.
+async fn forward_window_size(client: Client, container_id: String) -> eyre::Result<()> {
+    let mut window_changes = signal(SignalKind::window_change())?;
+    loop {
+        if let Some((height, width)) = terminal::window_size() {
+            client
+                .resize_container(&container_id, ResizeArgs { height, width })
+                .await?;
+        }
+        if window_changes.recv().await.is_none() {
+            return Ok(());
+        }
+    }
+}
+
+async fn print_published_ports(
+    client: &Client,
+    project: &Project,
+    container_type: ContainerType,
+) -> eyre::Result<()> {
+    let container = find_container(client, project, container_type).await?;
+    let ports = container.and_then(|container| container.ports).unwrap_or_default();
+    for port in ports {
+        if let Some(public_port) = port.public_port {
+            eprintln!(
+                "Publishing {}:{} -> {}/{}",
+                port.ip.as_deref().unwrap_or("0.0.0.0"),
+                public_port,
+                port.private_port,
+                port._type.as_str(),
+            );
+        }
+    }
     Ok(())
 }
 
-async fn cargo_check(client: &Client, project_name: &str, args: Vec<String>) -> eyre::Result<()> {
-    let cargo_cmd = make_cargo_cmd(false, args);
-    ephemeral_exec(client, project_name, cargo_cmd, ContainerType::Build).await?;
+/// Docker reports exit codes as an i64, but a process can only exit with an i32.
+/// Anything that doesn't fit is reported as a generic failure.
+fn exit_code(status_code: i64) -> i32 {
+    i32::try_from(status_code).unwrap_or(1)
+}
 
-    Ok(())
+/// Runs a cargo command in the Build container in two phases.
+///
+/// Dependencies are downloaded by `cargo fetch` in a container with networking. The
+/// command itself then runs with `--offline`, in a container that only has networking
+/// if `options` asks for it. Build scripts and procedural macros therefore can't reach
+/// the network unless the user opted in. The two phases share the project's dependency cache.
+async fn fetch_then_exec_offline(
+    client: &Client,
+    project: &Project,
+    config: &Config,
+    mut args: Vec<String>,
+    options: &ExecOptions,
+) -> eyre::Result<i32> {
+    let cache = DependencyCache::create(client, project, config.persist_cache()).await?;
+    let deadline = command_deadline(config);
+
+    let result = async {
+        let fetch_options = ExecOptions {
+            mounts: cache.mounts(),
+            deadline,
+            ..Default::default()
+        };
+        let fetch_cmd = make_cargo_cmd(false, fetch_args(&args));
+        let exit_code = ephemeral_exec(client, project, config, fetch_cmd, ContainerType::Build, &fetch_options).await?;
+        if exit_code != 0 {
+            return Ok(exit_code);
+        }
+
+        let mut options = options.clone();
+        options.mounts.extend(cache.mounts());
+        options.read_only_project = config.read_only_project();
+        options.deadline = deadline;
+        args.insert(1, "--offline".to_string());
+        let cargo_cmd = make_cargo_cmd(false, args);
+        ephemeral_exec(client, project, config, cargo_cmd, ContainerType::Build, &options).await
+    }
+    .await;
+
+    cache.release(client).await;
+
+    result
+}
+
+/// The arguments for the `cargo fetch` that precedes `args`. Only the flags that change
+/// which dependencies are resolved are kept.
+fn fetch_args(args: &[String]) -> Vec<String> {
+    let mut fetch_args = vec!["fetch".to_string()];
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--" => break,
+            "--locked" | "--frozen" => fetch_args.push(arg.clone()),
+            "--manifest-path" => {
+                fetch_args.push(arg.clone());
+                fetch_args.extend(args.next().cloned());
+            }
+            arg if arg.starts_with("--manifest-path=") => fetch_args.push(arg.to_string()),
+            _ => {}
+        }
+    }
+    fetch_args
+}
+
+/// Options for the second phase of `build` and `check`, which only has networking if the
+/// user asked for it with `--allow-network` or `network.allow`.
+fn build_options(allow_network: bool) -> ExecOptions {
+    ExecOptions {
+        network_disabled: !allow_network,
+        ..Default::default()
+    }
+}
+
+async fn cargo_build(
+    client: &Client,
+    project: &Project,
+    config: &Config,
+    args: Vec<String>,
+    allow_network: bool,
+) -> eyre::Result<i32> {
+    fetch_then_exec_offline(client, project, config, args, &build_options(allow_network)).await
 }
 
-fn insert_after(args: &mut Vec<String>, needle: &str, insert: String) {
-    let index = args.iter().position(|arg| arg == needle).unwrap();
-    args.insert(index + 1, insert.to_string());
+async fn cargo_check(
+    client: &Client,
+    project: &Project,
+    config: &Config,
+    args: Vec<String>,
+    allow_network: bool,
+) -> eyre::Result<i32> {
+    fetch_then_exec_offline(client, project, config, args, &build_options(allow_network)).await
+}
+
+/// Tests are compiled and run in the Build container. Unlike a build, running tests
+/// executes arbitrary code from every dependency, so networking is off unless the
+/// user explicitly asks for it. Arguments after `--` are passed on to libtest by cargo,
+/// and stdin is forwarded for tests that read from it.
+async fn cargo_test(
+    client: &Client,
+    project: &Project,
+    config: &Config,
+    args: Vec<String>,
+    allow_network: bool,
+) -> eyre::Result<i32> {
+    let options = ExecOptions {
+        network_disabled: !allow_network,
+        open_stdin: true,
+        ..Default::default()
+    };
+    fetch_then_exec_offline(client, project, config, args, &options).await
 }
 
-async fn cargo_publish(client: &Client, project_name: &str, mut args: Vec<String>) -> eyre::Result<()> {
-    let cargo_cmd = make_cargo_cmd(false, args);
-    // First verify the package unless we are told not to
-    if !cargo_cmd.iter().any( |a| a == "--no-verify") {
-        let mut cargo_cmd = cargo_cmd.clone();
-        // We only want to verify, so we ensure that `dry-run` is present in the args
-        if !cargo_cmd.iter().any(|a| a == "--dry-run") {
-            insert_after(&mut cargo_cmd, "publish", "--dry-run".to_string());
+/// Builds and runs the binary in the Build container. Stdin is forwarded to the
+/// program, and ports are only reachable from the host if they are listed with `--port`,
+/// which behaves like `docker run --publish`. Publishing a port requires networking, so
+/// the container only has networking if ports were listed or `--allow-network` was passed.
+async fn cargo_run(
+    client: &Client,
+    project: &Project,
+    config: &Config,
+    args: Vec<String>,
+    ports: Vec<PublishedPort>,
+    allow_network: bool,
+) -> eyre::Result<i32> {
+    let options = ExecOptions {
+        network_disabled: !allow_network && ports.is_empty(),
+        open_stdin: true,
+        ports,
+        ..Default::default()
+    };
+    fetch_then_exec_offline(client, project, config, args, &options).await
+}
+
+async fn cargo_publish(client: &Client, project: &Project, config: &Config, args: Vec<String>) -> eyre::Result<i32> {
+    let flags = || args.iter().take_while(|arg| *arg != "--");
+    if flags().any(|arg| arg == "--index" || arg.starts_with("--index=")) {
+        eyre::bail!("--index isn't supported, configure the registry in .cargo/config.toml and pass --registry");
+    }
+    if flags().any(|arg| arg == "--token" || arg.starts_with("--token=")) {
+        eyre::bail!("--token would put the token on the container's command line, store it with `cargo-sandbox login` instead");
+    }
+    let dry_run = flags().any(|arg| arg == "--dry-run");
+    let verify = !flags().any(|arg| arg == "--no-verify");
+    let deadline = command_deadline(config);
+
+    // Cargo in the Build container packages against the registry's index
+    let registry = match registry_arg(&args) {
+        Some(name) => Some(Registry::locate(project, &name)?),
+        None => None,
+    };
+    let env: Vec<String> = registry.iter().map(Registry::index_env).collect();
+
+    // `cargo package` takes the same arguments, other than these
+    let mut package_args: Vec<String> = args
+        .into_iter()
+        .filter(|arg| arg != "--dry-run" && arg != "--no-verify")
+        .collect();
+    package_args[0] = "package".to_string();
+
+    let packaged = match package_crate(client, project, config, &package_args, &env, deadline).await? {
+        Ok(packaged) => packaged,
+        Err(exit_code) => return Ok(exit_code),
+    };
+    println!("Packaged {} with SHA-256 {}", packaged.file_name, packaged.sha256);
+
+    if verify {
+        // Verifying runs build scripts, but the `.crate` is already out of their reach.
+        // `cargo package` queries the registry, so it can't run `--offline`
+        let cargo_cmd = make_cargo_cmd(false, package_args);
+        let options = ExecOptions {
+            read_only_project: config.read_only_project(),
+            env: env.clone(),
+            deadline,
+            ..Default::default()
+        };
+        let exit_code = ephemeral_exec(client, project, config, cargo_cmd, ContainerType::Build, &options).await?;
+        // Never publish a package that failed to verify
+        if exit_code != 0 {
+            return Ok(exit_code);
         }
-        ephemeral_exec(client, project_name, cargo_cmd, ContainerType::Build).await?;
     }
 
-    // Second, publish the package unless we are told not to
-    if !cargo_cmd.iter().any(|a| a == "--dry-run") {
-        let mut cargo_cmd = cargo_cmd.clone();
-        // We only want to publish, so we ensure that `no-verify` is present in the args
-        if !cargo_cmd.iter().any(|a| a == "--no-verify") {
-            insert_after(&mut cargo_cmd, "publish", "--very-verify".to_string());
+    if dry_run {
+        println!("Not uploading {} because of --dry-run", packaged.file_name);
+        return Ok(0);
+    }
+
+    // The token only ever exists in the Publish container, as a read-only file
+    let credentials = PublishCredentials::create(registry.as_ref().map(|registry| registry.name.as_str()))?;
+    // The root filesystem is read-only, so the `.crate` is copied into a volume
+    let upload_volume = cache::scratch_volume(client, project, "upload").await?;
+    let options = ExecOptions {
+        mounts: vec![credentials.mount()?, Mount::volume(&upload_volume, DOCKER_UPLOAD_DIR)],
+        archives: vec![(DOCKER_UPLOAD_DIR.to_string(), packaged.upload_archive(registry.as_ref())?)],
+        deadline,
+        ..Default::default()
+    };
+    let upload_cmd = packaged.upload_command(registry.as_ref());
+    let result = ephemeral_exec(client, project, config, upload_cmd, ContainerType::Publish, &options).await;
+
+    if let Err(e) = client.remove_volume(&upload_volume, true).await {
+        eprintln!("Failed to remove upload volume {upload_volume}: {e}");
+    }
+    result
+}
+
+/// Runs `cargo package --no-verify` in the Build container and reads the `.crate` back out.
+/// No build script runs before the `.crate` is out of the sandbox, and the target directory
+/// is a fresh volume, so the `.crate` is exactly what cargo packaged from the source.
+async fn package_crate(
+    client: &Client,
+    project: &Project,
+    config: &Config,
+    package_args: &[String],
+    env: &[String],
+    deadline: Option<Instant>,
+) -> eyre::Result<Result<PackagedCrate, i32>> {
+    let volume = cache::scratch_volume(client, project, "package").await?;
+    let package_mount = Mount::volume(&volume, DOCKER_PACKAGE_DIR);
+
+    let result = async {
+        let mut args = package_args.to_vec();
+        args.splice(1..1, ["--no-verify".to_string(), "--target-dir".to_string(), DOCKER_PACKAGE_DIR.to_string()]);
+        let options = ExecOptions {
+            mounts: vec![package_mount.clone()],
+            read_only_project: config.read_only_project(),
+            env: env.to_vec(),
+            deadline,
+            ..Default::default()
+        };
+        let exit_code = ephemeral_exec(client, project, config, make_cargo_cmd(false, args), ContainerType::Build, &options).await?;
+        if exit_code != 0 {
+            return Ok(Err(exit_code));
         }
-        ephemeral_exec(client, project_name, cargo_cmd, ContainerType::Publish).await?;
+
+        // That container is gone, so the volume is read through one that is never started
+        let options = ExecOptions {
+            network_disabled: true,
+            mounts: vec![package_mount.clone()],
+            ..Default::default()
+        };
+        let reader = create_container(client, project, config, ContainerType::Build, vec!["true".to_string()], &options).await?;
+        let archive = client
+            .get_archive(&reader.id, ArchiveArgs { path: format!("{DOCKER_PACKAGE_DIR}/package") })
+            .await;
+        client.remove_container(reader.id, true, true).await?;
+        Ok(Ok(PackagedCrate::from_package_archive(&archive?)?))
     }
-    Ok(())
+    .await;
+
+    if let Err(e) = client.remove_volume(&volume, true).await {
+        eprintln!("Failed to remove package volume {volume}: {e}");
+    }
+    result
 }
 
-fn get_env() -> Vec<String> {
-    std::env::vars()
-        .filter_map(|(key, value)| {
-            if should_pass_key(&key) {
-                Some(format!("{}={}", key, value))
-            } else {
-                None
-            }
-        })
-        .collect()
+/// The value of `--registry`, if any.
+fn registry_arg(args: &[String]) -> Option<String> {
+    let mut args = args.iter().take_while(|arg| *arg != "--");
+    while let Some(arg) = args.next() {
+        if arg == "--registry" {
+            return args.next().cloned();
+        }
+        if let Some(registry) = arg.strip_prefix("--registry=") {
+            return Some(registry.to_string());
+        }
+    }
+    None
 }
 
-fn should_pass_key(key: &str) -> bool {
-    const EQUALS: &[&str] = &["CARGO_BUILD_JOBS", "RUST_RECURSION_COUNT"];
-    EQUALS.contains(&key)
+/// Stores a registry token for `cargo-sandbox publish`, like `cargo login` does but in our
+/// own credential store, so that cargo commands on the host and in the Build container
+/// can't read it.
+fn cargo_login(args: &[String]) -> eyre::Result<i32> {
+    let registry = registry_arg(args);
+    let mut token = None;
+    let mut rest = args.iter().skip(1);
+    while let Some(arg) = rest.next() {
+        if arg == "--registry" {
+            rest.next();
+        } else if !arg.starts_with("--registry=") {
+            token = Some(arg.clone());
+        }
+    }
+    let token = match token {
+        Some(token) => token,
+        None => {
+            eprintln!(
+                "Please paste the token for {} below",
+                registry.as_deref().unwrap_or("crates.io")
+            );
+            terminal::read_secret_line()?
+        }
+    };
+    if token.is_empty() {
+        eprintln!("No token given");
+        return Ok(1);
+    }
+
+    let path = Credentials::store(registry.as_deref(), token)?;
+    println!("Stored the token in {}", path.display());
+    Ok(0)
 }
 
-fn get_project_name() -> String {
-    let current_dir = std::env::current_dir().unwrap();
-    let project_name = current_dir
-        .components()
-        .last()
-        .unwrap()
-        .as_os_str()
-        .to_str()
-        .unwrap();
-    println!("project name: {}", project_name);
-    project_name.to_string()
+/// Prints what the containers for `args` would be able to reach, without running anything.
+fn dry_run(
+    project: &Project,
+    config: &Config,
+    args: &[String],
+    ports: &[PublishedPort],
+    allow_network: bool,
+) -> eyre::Result<i32> {
+    let Some(command) = args.first() else {
+        println!("Usage: cargo-sandbox dry-run <command> [args]...");
+        return Ok(1);
+    };
+    let fetch_only = "only while fetching dependencies";
+    let containers: &[(ContainerType, &str)] = match command.as_str() {
+        "build" | "check" | "test" if allow_network => &[(ContainerType::Build, "enabled")],
+        "build" | "check" => &[(ContainerType::Build, fetch_only)],
+        "run" if allow_network || !ports.is_empty() => &[(ContainerType::Build, "enabled")],
+        "test" | "run" => &[(ContainerType::Build, fetch_only)],
+        "publish" => &[(ContainerType::Build, "enabled"), (ContainerType::Publish, "enabled")],
+        unknown => {
+            println!("Unknown command: {unknown}");
+            return Ok(1);
+        }
+    };
+
+    println!("cargo {}", args.join(" "));
+    for &(container_type, network) in containers {
+        let toolchain = match container_type {
+            ContainerType::Build => Toolchain::detect(project)?,
+            ContainerType::Publish => None,
+        };
+        let image = images::select_image(config, container_type, toolchain.as_ref());
+
+        println!("{} container:", container_type.as_str());
+        println!("  image: {}", image.name);
+        if let Some(toolchain) = toolchain.filter(|toolchain| toolchain.rust_image_version().is_none()) {
+            println!("  toolchain: {}, installed into a shared read-only volume", toolchain.channel);
+        }
+        println!("  network: {network}");
+        let relaxations = match container_type {
+            ContainerType::Build => config.security_relaxations(),
+            ContainerType::Publish => Vec::new(),
+        };
+        if relaxations.is_empty() {
+            println!("  security: no capabilities, read-only root filesystem, no-new-privileges");
+        } else {
+            println!("  security: relaxed by configuration: {}", relaxations.join(", "));
+        }
+        for port in ports.iter().filter(|_| command == "run") {
+            let host_port = port.host_port.map_or_else(|| "<any free port>".to_string(), |host_port| host_port.to_string());
+            println!("  published port: {}:{host_port} -> {}", port.host_ip, port.container_key());
+        }
+        let mounts = host_mounts(project, config, container_type, config.read_only_project())?;
+        println!("  host paths:{}", if mounts.is_empty() { " none" } else { "" });
+        for mount in mounts {
+            let access = if mount.read_only { "read-only" } else { "read-write" };
+            println!("    {} -> {} ({access})", mount.source, mount.target);
+        }
+        let memory = config.limits.memory.map_or_else(|| "unlimited".to_string(), |memory| format!("{} bytes", memory.0));
+        let timeout = config.limits.timeout.map_or_else(|| "none".to_string(), |timeout| timeout.to_string());
+        println!("  limits: memory {memory}, {} processes, timeout {timeout}", config.pids_limit());
+        let env = passed_env(config, container_type);
+        let names: Vec<&str> = env.iter().filter_map(|var| var.split_once('=')).map(|(name, _)| name).collect();
+        println!("  environment: {}", if names.is_empty() { "none".to_string() } else { names.join(", ") });
+    }
+    Ok(0)
+}
+
+/// Copies `paths`, relative to the target directory, out of the project's target volume
+/// and into `target` on the host. This is the only way that build outputs leave the
+/// sandbox when the project is mounted read-only.
+async fn artifacts_pull(client: &Client, project: &Project, config: &Config, paths: &[String]) -> eyre::Result<i32> {
+    for path in paths {
+        let is_relative = Path::new(path)
+            .components()
+            .all(|component| matches!(component, Component::Normal(_)));
+        if !is_relative {
+            eyre::bail!("Artifact paths must be relative to the target directory and may not contain `..`: {path}");
+        }
+    }
+
+    find_and_remove_container(client, project, ContainerType::Build).await?;
+
+    // The container never runs, it only gives us access to the volume
+    let options = ExecOptions {
+        network_disabled: true,
+        read_only_project: true,
+        ..Default::default()
+    };
+    let container = create_container(client, project, config, ContainerType::Build, vec!["true".to_string()], &options).await?;
+
+    let result = async {
+        let host_target_dir = project.root.join("target");
+        for path in paths {
+            let archive = client
+                .get_archive(&container.id, ArchiveArgs { path: format!("{DOCKER_TARGET_DIR}/{path}") })
+                .await?;
+            // The archive's root is the last component of `path`
+            let destination = match Path::new(path).parent() {
+                Some(parent) => host_target_dir.join(parent),
+                None => host_target_dir.clone(),
+            };
+            std::fs::create_dir_all(&destination)?;
+            unpack_artifacts(&archive, &destination)
+                .wrap_err_with(|| format!("Failed to extract {path} into {}", destination.display()))?;
+            println!("Pulled target/{path}");
+        }
+        Ok(0)
+    }
+    .await;
+
+    client.remove_container(container.id, true, true).await?;
+
+    result
+}
+
+/// Extracts an archive that the container produced into `destination` on the host. The
+/// container controls its contents, so nothing is extracted unless every entry is a regular
+/// file or directory that stays inside `destination`. A symlink or hardlink could otherwise
+/// point anywhere on the host.
+fn unpack_artifacts(archive: &[u8], destination: &Path) -> eyre::Result<()> {
+    for entry in tar::Archive::new(archive).entries()? {
+        let entry = entry?;
+        let path = entry.path()?.into_owned();
+        let entry_type = entry.header().entry_type();
+        if !entry_type.is_file() && !entry_type.is_dir() {
+            eyre::bail!("Refusing to extract {}, it is neither a regular file nor a directory", path.display());
+        }
+        let stays_inside = path
+            .components()
+            .all(|component| matches!(component, Component::Normal(_) | Component::CurDir));
+        if !stays_inside {
+            eyre::bail!("Refusing to extract {}, it would end up outside of target/", path.display());
+        }
+    }
+
+    for entry in tar::Archive::new(archive).entries()? {
+        entry?.unpack_in(destination)?;
+    }
+    Ok(())
 }
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let mut args: Vec<String> = std::env::args().collect();
-    println!("{:?}", args);
+    let mut argv: Vec<String> = std::env::args().skip(1).collect();
+
+    let sandbox_args = SandboxArgs::extract(&mut argv)?;
 
-    let argv = args.split_off(1);
-    let _argc = args;
-    println!("{_argc:?} - {argv:?}");
+    // Logging in doesn't involve a project or the docker daemon
+    if argv.first().map(String::as_str) == Some("login") {
+        std::process::exit(cargo_login(&argv)?);
+    }
+    signals::exit_unless_forwarded()?;
+
+    let project = Project::locate(&std::env::current_dir()?)?;
+    let mut config = Config::load(&project.root)?;
+    config.override_limits(sandbox_args.limits);
+    let relaxations = config.security_relaxations();
+    if !relaxations.is_empty() {
+        eprintln!("sandbox relaxed by configuration: {}", relaxations.join(", "));
+    }
+    let docker_socket = docker_host::resolve_docker_socket(sandbox_args.docker_host.as_deref(), &config)?;
+    let client = Client::local(docker_socket);
+    let allow_network = sandbox_args.allow_network || config.allow_network();
 
-    match argv[0].as_ref() {
+    let exit_code = match argv[0].as_ref() {
         "build" => {
-            let project_name = get_project_name();
-            let client = Client::local("/var/run/docker.sock");
-            cargo_build(&client, &project_name, argv).await?;
+            cargo_build(&client, &project, &config, argv, allow_network).await?
         }
         "check" => {
-            let project_name = get_project_name();
-            let client = Client::local("/var/run/docker.sock");
-            cargo_check(&client, &project_name, argv).await?;
+            cargo_check(&client, &project, &config, argv, allow_network).await?
+        }
+        "test" => {
+            cargo_test(&client, &project, &config, argv, allow_network).await?
+        }
+        "run" => {
+            cargo_run(&client, &project, &config, argv, sandbox_args.ports, allow_network).await?
         }
         "publish" => {
-            let project_name = get_project_name();
-            let client = Client::local("/var/run/docker.sock");
-            cargo_publish(&client, &project_name, argv).await?;
-        }
-        // "login" => {
-        //     let project_name = get_project_name();
-        //     let client = Client::local("/var/run/docker.sock");
-        //     cargo_login(&client, &project_name, argv).await?;
-        // }
+            cargo_publish(&client, &project, &config, argv).await?
+        }
+        "cache" => match argv.get(1).map(String::as_str) {
+            Some("prune") => {
+                let all_projects = argv[2..].iter().any(|arg| arg == "--all");
+                let project = (!all_projects).then_some(&project);
+                cache::prune(&client, project).await?;
+                0
+            }
+            other => {
+                println!("Unknown cache command: {}", other.unwrap_or_default());
+                1
+            }
+        },
+        "dry-run" => {
+            dry_run(&project, &config, &argv[1..], &sandbox_args.ports, allow_network)?
+        }
+        "artifacts" => match argv.get(1).map(String::as_str) {
+            Some("pull") if argv.len() > 2 => {
+                artifacts_pull(&client, &project, &config, &argv[2..]).await?
+            }
+            Some("pull") => {
+                println!("Usage: cargo-sandbox artifacts pull <path>...");
+                1
+            }
+            other => {
+                println!("Unknown artifacts command: {}", other.unwrap_or_default());
+                1
+            }
+        },
+        "config" => match argv.get(1).map(String::as_str) {
+            Some("show") => {
+                print!("{}", config.show());
+                0
+            }
+            other => {
+                println!("Unknown config command: {}", other.unwrap_or_default());
+                1
+            }
+        },
         unknown => {
             println!("Unknown command: {unknown}");
+            1
         }
+    };
+
+    std::process::exit(exit_code)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
     }
 
-    Ok(())
+    #[test]
+    fn fetch_args_keep_only_what_changes_resolution() {
+        let args = strings(&["build", "--release", "--locked", "--manifest-path", "crates/core/Cargo.toml", "-p", "core"]);
+        assert_eq!(fetch_args(&args), strings(&["fetch", "--locked", "--manifest-path", "crates/core/Cargo.toml"]));
+
+        let args = strings(&["test", "--frozen", "--manifest-path=Cargo.toml", "--", "--locked"]);
+        assert_eq!(fetch_args(&args), strings(&["fetch", "--frozen", "--manifest-path=Cargo.toml"]));
+    }
+
+    #[test]
+    fn registry_arg_in_either_form() {
+        assert_eq!(registry_arg(&strings(&["publish", "--registry", "internal"])).as_deref(), Some("internal"));
+        assert_eq!(registry_arg(&strings(&["login", "--registry=internal", "token"])).as_deref(), Some("internal"));
+        assert_eq!(registry_arg(&strings(&["publish", "--dry-run"])), None);
+        // Arguments after `--` belong to something else
+        assert_eq!(registry_arg(&strings(&["publish", "--", "--registry", "internal"])), None);
+    }
+
+    #[test]
+    fn allow_network_reaches_build_options() {
+        assert!(build_options(false).network_disabled);
+        assert!(!build_options(true).network_disabled);
+    }
+
+    #[test]
+    fn unpack_artifacts_rejects_symlinks() {
+        let mut archive = tar::Builder::new(Vec::new());
+        let mut file = tar::Header::new_gnu();
+        file.set_size(4);
+        file.set_mode(0o644);
+        archive.append_data(&mut file, "release/app", &b"\x7fELF"[..]).unwrap();
+        let mut link = tar::Header::new_gnu();
+        link.set_entry_type(tar::EntryType::Symlink);
+        link.set_size(0);
+        archive.append_link(&mut link, "release/passwd", "/etc/passwd").unwrap();
+        let archive = archive.into_inner().unwrap();
+
+        let destination = std::env::temp_dir().join(format!("cargo-sandbox-test-{}", std::process::id()));
+        std::fs::create_dir_all(&destination).unwrap();
+        let result = unpack_artifacts(&archive, &destination);
+        let extracted = destination.join("release").exists();
+        std::fs::remove_dir_all(&destination).unwrap();
+
+        assert!(result.is_err());
+        assert!(!extracted, "nothing is extracted from an archive with a symlink");
+    }
 }
@@ -0,0 +1,306 @@
+use std::collections::{BTreeSet, HashSet, VecDeque};
+use std::path::{Component, Path, PathBuf};
+
+use crate::config::read_optional;
+use crate::env_filter;
+use crate::project::{declares_workspace, Project};
+
+/// The tables of a manifest that can hold dependencies, besides the same tables under
+/// `target.<cfg>`.
+const DEPENDENCY_TABLES: &[&str] = &["dependencies", "dev-dependencies", "build-dependencies"];
+
+/// A directory outside the workspace root that the build needs, and where it is mounted.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ExternalPath {
+    pub host_path: PathBuf,
+    pub container_path: String,
+}
+
+/// Finds the `path` dependencies, and `paths` overrides in `.cargo/config.toml`, that live
+/// outside the workspace root. Manifests are read the way `cargo metadata` would resolve
+/// them, following path dependencies of path dependencies, but without running cargo.
+///
+/// Only the outermost directories are returned, so that nothing is mounted twice. Each is
+/// mounted at the same location relative to the project as on the host.
+pub fn external_paths(project: &Project, container_root: &str) -> eyre::Result<Vec<ExternalPath>> {
+    let mut external = BTreeSet::new();
+    let mut visited = HashSet::new();
+    let mut manifest_dirs = VecDeque::from([project.root.clone()]);
+    for path in config_paths(project)? {
+        manifest_dirs.push_back(path);
+    }
+
+    while let Some(dir) = manifest_dirs.pop_front() {
+        // Missing directories are left for cargo to report
+        let Ok(dir) = dir.canonicalize() else {
+            continue;
+        };
+        if !visited.insert(dir.clone()) {
+            continue;
+        }
+
+        // Only a directory that cargo would accept as a package or workspace is ever mounted,
+        // whatever a manifest or `.cargo/config.toml` claims is a dependency
+        let manifest_path = dir.join("Cargo.toml");
+        let Some(contents) = read_optional(&manifest_path)? else {
+            continue;
+        };
+        let manifest: toml::Value = toml::from_str(&contents)
+            .map_err(|e| eyre::eyre!("Invalid manifest {}\n{e}", manifest_path.display()))?;
+        if manifest.get("package").is_none() && manifest.get("workspace").is_none() {
+            eyre::bail!("{} has neither a [package] nor a [workspace]", manifest_path.display());
+        }
+
+        if !dir.starts_with(&project.root) {
+            // Cargo also reads the workspace a path dependency belongs to, if it has one
+            external.insert(enclosing_workspace(&dir, &project.root)?.unwrap_or_else(|| dir.clone()));
+        }
+        for path in manifest_paths(&manifest) {
+            manifest_dirs.push_back(dir.join(path));
+        }
+        for member in workspace_members(&dir, &manifest)? {
+            manifest_dirs.push_back(member);
+        }
+    }
+
+    let outermost: Vec<PathBuf> = external
+        .iter()
+        .filter(|path| !external.iter().any(|other| other != *path && path.starts_with(other)))
+        .cloned()
+        .collect();
+
+    outermost
+        .into_iter()
+        .map(|host_path| {
+            if project.root.starts_with(&host_path) {
+                eyre::bail!(
+                    "The path dependency {} contains the workspace root {}, so it can't be mounted next to it",
+                    host_path.display(),
+                    project.root.display(),
+                );
+            }
+            let container_path = container_path(&project.root, container_root, &host_path)?;
+            Ok(ExternalPath { host_path, container_path })
+        })
+        .collect()
+}
+
+/// The nearest directory above `dir` whose manifest declares a `[workspace]`, up to the
+/// first directory that also contains `root`.
+fn enclosing_workspace(dir: &Path, root: &Path) -> eyre::Result<Option<PathBuf>> {
+    for ancestor in dir.ancestors().skip(1) {
+        if root.starts_with(ancestor) {
+            break;
+        }
+        let Some(contents) = read_optional(&ancestor.join("Cargo.toml"))? else {
+            continue;
+        };
+        if declares_workspace(&contents) {
+            return Ok(Some(ancestor.to_path_buf()));
+        }
+    }
+    Ok(None)
+}
+
+/// `paths` from `.cargo/config.toml` or `.cargo/config` between the current directory and
+/// the workspace root. Configuration further up isn't visible inside the sandbox.
+fn config_paths(project: &Project) -> eyre::Result<Vec<PathBuf>> {
+    let mut paths = Vec::new();
+    let current_dir = project.root.join(&project.relative_dir);
+    for dir in current_dir.ancestors().take_while(|dir| dir.starts_with(&project.root)) {
+        for file_name in ["config.toml", "config"] {
+            let config_path = dir.join(".cargo").join(file_name);
+            let Some(contents) = read_optional(&config_path)? else {
+                continue;
+            };
+            let config: toml::Value = toml::from_str(&contents)
+                .map_err(|e| eyre::eyre!("Invalid cargo configuration {}\n{e}", config_path.display()))?;
+            let entries = config.get("paths").and_then(toml::Value::as_array);
+            for path in entries.into_iter().flatten().filter_map(toml::Value::as_str) {
+                paths.push(dir.join(path));
+            }
+            break;
+        }
+    }
+    Ok(paths)
+}
+
+/// Every `path` of a dependency, a `[patch]` or a `[replace]` in `manifest`.
+fn manifest_paths(manifest: &toml::Value) -> Vec<String> {
+    let mut tables = Vec::new();
+    for name in DEPENDENCY_TABLES {
+        tables.extend(manifest.get(name));
+    }
+    if let Some(targets) = manifest.get("target").and_then(toml::Value::as_table) {
+        for target in targets.values() {
+            for name in DEPENDENCY_TABLES {
+                tables.extend(target.get(name));
+            }
+        }
+    }
+    tables.extend(manifest.get("workspace").and_then(|workspace| workspace.get("dependencies")));
+    if let Some(patches) = manifest.get("patch").and_then(toml::Value::as_table) {
+        tables.extend(patches.values());
+    }
+    tables.extend(manifest.get("replace"));
+
+    tables
+        .into_iter()
+        .filter_map(toml::Value::as_table)
+        .flat_map(|table| table.values())
+        .filter_map(|dependency| dependency.get("path").and_then(toml::Value::as_str))
+        .map(str::to_string)
+        .collect()
+}
+
+/// The member directories of the workspace declared in `manifest`, if any. Members may
+/// use `*` in any component.
+fn workspace_members(dir: &Path, manifest: &toml::Value) -> eyre::Result<Vec<PathBuf>> {
+    let members = manifest
+        .get("workspace")
+        .and_then(|workspace| workspace.get("members"))
+        .and_then(toml::Value::as_array);
+
+    let mut directories = Vec::new();
+    for pattern in members.into_iter().flatten().filter_map(toml::Value::as_str) {
+        let mut candidates = vec![dir.to_path_buf()];
+        for component in Path::new(pattern).components() {
+            let component = component.as_os_str().to_string_lossy();
+            if !component.contains('*') {
+                candidates = candidates.into_iter().map(|candidate| candidate.join(&*component)).collect();
+                continue;
+            }
+            let mut matched = Vec::new();
+            for candidate in candidates {
+                let Ok(entries) = std::fs::read_dir(&candidate) else {
+                    continue;
+                };
+                for entry in entries {
+                    let entry = entry?;
+                    if env_filter::matches(&component, &entry.file_name().to_string_lossy()) {
+                        matched.push(entry.path());
+                    }
+                }
+            }
+            candidates = matched;
+        }
+        directories.extend(candidates);
+    }
+    Ok(directories)
+}
+
+/// Where `host_path` goes in the container, so that it is at the same path relative to the
+/// project as on the host. The project is mounted at `container_root`.
+fn container_path(root: &Path, container_root: &str, host_path: &Path) -> eyre::Result<String> {
+    let common = root
+        .ancestors()
+        .find(|ancestor| host_path.starts_with(ancestor))
+        .unwrap_or_else(|| Path::new("/"));
+    let levels_up = root.strip_prefix(common)?.components().count();
+    let below = host_path.strip_prefix(common)?;
+
+    // Going further up than `/home` would put dependencies among system directories
+    let depth = Path::new(container_root)
+        .components()
+        .filter(|component| matches!(component, Component::Normal(_)))
+        .count();
+    if levels_up >= depth {
+        eyre::bail!(
+            "The path dependency {} is too far outside the workspace root {} to be mounted",
+            host_path.display(),
+            root.display(),
+        );
+    }
+
+    let mut container_path = PathBuf::from(container_root);
+    for _ in 0..levels_up {
+        container_path.pop();
+    }
+    container_path.push(below);
+    Ok(container_path.to_string_lossy().into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Writes `files`, relative to a fresh temporary directory, and returns that directory.
+    fn write_tree(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("cargo-sandbox-{name}-{}", std::process::id()));
+        for (path, contents) in files {
+            let path = dir.join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, contents).unwrap();
+        }
+        dir.canonicalize().unwrap()
+    }
+
+    #[test]
+    fn container_path_keeps_the_relative_location() {
+        let root = Path::new("/src/work/app");
+        let container_root = "/home/cargo-sandbox-user/app";
+        assert_eq!(
+            container_path(root, container_root, Path::new("/src/work/shared")).unwrap(),
+            "/home/cargo-sandbox-user/shared",
+        );
+        assert_eq!(
+            container_path(root, container_root, Path::new("/src/vendor/lib")).unwrap(),
+            "/home/vendor/lib",
+        );
+        // That would be `/vendor`, among the container's system directories
+        assert!(container_path(root, container_root, Path::new("/opt/vendor")).is_err());
+    }
+
+    #[test]
+    fn follows_path_dependencies_and_mounts_outermost_directories() {
+        let dir = write_tree(
+            "outermost",
+            &[
+                (
+                    "app/Cargo.toml",
+                    "[workspace]\nmembers = [\"crates/*\"]\n\n[patch.crates-io]\nserde = { path = \"../forks/serde\" }\n",
+                ),
+                ("app/crates/core/Cargo.toml", "[package]\nname = \"core\"\n\n[dependencies]\nutil = { path = \"../../../libs/util\" }\n"),
+                // Reached only through `util`, and inside `libs` which is mounted anyway
+                ("libs/util/Cargo.toml", "[package]\nname = \"util\"\n\n[dependencies]\nhelper = { path = \"../helper\" }\n"),
+                ("libs/helper/Cargo.toml", "[package]\nname = \"helper\"\n"),
+                ("libs/Cargo.toml", "[workspace]\nmembers = [\"util\", \"helper\"]\n"),
+                ("forks/serde/Cargo.toml", "[package]\nname = \"serde\"\n"),
+            ],
+        );
+        let project = Project::locate(&dir.join("app")).unwrap();
+        let external = external_paths(&project, "/home/cargo-sandbox-user/app");
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let host_paths: Vec<PathBuf> = external.unwrap().into_iter().map(|external| external.host_path).collect();
+        assert_eq!(host_paths, vec![dir.join("forks/serde"), dir.join("libs")]);
+    }
+
+    #[test]
+    fn directories_without_a_manifest_are_never_mounted() {
+        let dir = write_tree(
+            "no-manifest",
+            &[
+                (
+                    "app/Cargo.toml",
+                    "[package]\nname = \"app\"\n\n[dependencies]\nshared = { path = \"../shared\" }\nkeys = { path = \"../.ssh\" }\n",
+                ),
+                ("app/.cargo/config.toml", "paths = [\"../.aws\"]\n"),
+                ("shared/Cargo.toml", "[package]\nname = \"shared\"\n"),
+                (".ssh/id_ed25519", "secret"),
+                (".aws/credentials", "secret"),
+            ],
+        );
+        let project = Project::locate(&dir.join("app")).unwrap();
+        let external = external_paths(&project, "/home/cargo-sandbox-user/app");
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(
+            external.unwrap(),
+            vec![ExternalPath {
+                host_path: dir.join("shared"),
+                container_path: "/home/cargo-sandbox-user/shared".to_string(),
+            }],
+        );
+    }
+}
@@ -0,0 +1,80 @@
+use std::path::{Path, PathBuf};
+
+use eyre::WrapErr;
+use sha2::{Digest, Sha256};
+
+use crate::config::read_optional;
+
+/// The label holding `Project::id` on every container and volume of a project.
+pub const PROJECT_ID_LABEL: &str = "cargo-sandbox.project-id";
+/// The label holding `Project::name`, for humans only.
+pub const PROJECT_NAME_LABEL: &str = "cargo-sandbox.project-name";
+
+/// The workspace that cargo-sandbox runs in, and what its containers and volumes are named after.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Project {
+    /// The canonical path of the workspace root, which is mounted into the containers.
+    pub root: PathBuf,
+    /// The path of the current directory relative to `root`, so that commands run in the
+    /// same package as they would on the host.
+    pub relative_dir: PathBuf,
+    /// The name of the root directory.
+    pub name: String,
+    /// Derived from `root`, so that checkouts of the same name in different places never
+    /// share containers or volumes.
+    pub id: String,
+}
+
+impl Project {
+    /// Finds the workspace root the way cargo does: the nearest `Cargo.toml` that declares
+    /// a `[workspace]`, or the nearest `Cargo.toml` if none does.
+    pub fn locate(dir: &Path) -> eyre::Result<Project> {
+        let dir = dir
+            .canonicalize()
+            .wrap_err_with(|| format!("Failed to resolve {}", dir.display()))?;
+
+        let mut root = None;
+        for ancestor in dir.ancestors() {
+            let Some(manifest) = read_optional(&ancestor.join("Cargo.toml"))? else {
+                continue;
+            };
+            if declares_workspace(&manifest) {
+                root = Some(ancestor.to_path_buf());
+                break;
+            }
+            root.get_or_insert_with(|| ancestor.to_path_buf());
+        }
+        let Some(root) = root else {
+            eyre::bail!("Could not find Cargo.toml in {} or any parent directory", dir.display());
+        };
+
+        let name = match root.file_name() {
+            Some(name) => name.to_string_lossy().into_owned(),
+            None => "root".to_string(),
+        };
+        let digest = Sha256::digest(root.as_os_str().as_encoded_bytes());
+        let id = digest[..6].iter().map(|byte| format!("{byte:02x}")).collect();
+        let relative_dir = dir.strip_prefix(&root)?.to_path_buf();
+
+        Ok(Project {
+            root,
+            relative_dir,
+            name,
+            id,
+        })
+    }
+
+    /// The prefix of the names of this project's volumes, readable and unique.
+    pub fn volume_prefix(&self) -> String {
+        let name: String = self
+            .name
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() || c == '.' || c == '-' || c == '_' { c } else { '_' })
+            .collect();
+        format!("cargo-sandbox-{}-{}", name, self.id)
+    }
+}
+
+pub(crate) fn declares_workspace(manifest: &str) -> bool {
+    toml::from_str::<toml::Value>(manifest).is_ok_and(|manifest| manifest.get("workspace").is_some())
+}
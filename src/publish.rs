@@ -0,0 +1,345 @@
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use eyre::WrapErr;
+use flate2::read::GzDecoder;
+use serde_json::json;
+use sha2::{Digest, Sha256};
+
+use crate::cache::DOCKER_CARGO_HOME;
+use crate::config::read_optional;
+use crate::project::Project;
+
+/// The target directory of `cargo package` in the Build container, where a fresh volume
+/// is mounted for every publish.
+pub const DOCKER_PACKAGE_DIR: &str = "/home/cargo-sandbox-user/package";
+
+/// Where the `.crate` is copied to in the Publish container.
+pub const DOCKER_UPLOAD_DIR: &str = "/home/cargo-sandbox-user/upload";
+
+const CRATES_IO_API: &str = "https://crates.io";
+
+/// How dependencies from crates.io are identified when publishing to another registry.
+const CRATES_IO_INDEX: &str = "https://github.com/rust-lang/crates.io-index";
+
+/// Checks the `.crate` against the hash it had when it left the Build container, then
+/// uploads exactly those bytes to the registry's publish endpoint. The endpoint of an
+/// alternative registry is read from the `config.json` of its index. The token is passed
+/// to curl through a file descriptor rather than its arguments.
+///
+/// Arguments: the SHA-256 of the `.crate`, its file name and the index of the registry,
+/// which is empty for crates.io.
+const UPLOAD_SCRIPT: &str = r#"
+set -o errexit -o nounset -o pipefail
+cd "$UPLOAD_DIR"
+if ! echo "$1  $2" | sha256sum --check --status; then
+    echo "$2 does not match the SHA-256 it had when it was packaged, refusing to upload it" >&2
+    exit 1
+fi
+api_url() {
+    sed -n 's/.*"api" *: *"\([^"]*\)".*/\1/p' | head -n 1
+}
+case "$3" in
+    "")
+        api="$CRATES_IO_API" ;;
+    sparse+*)
+        index="${3#sparse+}"
+        api=$(curl --silent --show-error --fail "${index%/}/config.json" | api_url) ;;
+    *)
+        git clone --quiet --depth 1 "$3" /tmp/index
+        api=$(api_url < /tmp/index/config.json) ;;
+esac
+if [ -z "$api" ]; then
+    echo "The index $3 doesn't name an API to publish to" >&2
+    exit 1
+fi
+token=$(sed -n 's/^token = "\(.*\)"$/\1/p' "$CARGO_HOME/credentials.toml" | head -n 1)
+status=$(cat request-head "$2" | curl --silent --show-error \
+    --request PUT \
+    --header @<(printf 'Authorization: %s\n' "$token") \
+    --header 'Content-Type: application/octet-stream' \
+    --data-binary @- \
+    --output /tmp/response.json \
+    --write-out '%{http_code}' \
+    "${api%/}/api/v1/crates/new")
+cat /tmp/response.json
+echo
+[ "$status" = 200 ] && ! grep -q '"errors"' /tmp/response.json
+"#;
+
+/// The `.crate` produced by `cargo package` in the Build container, as it was read back
+/// out of the sandbox.
+pub struct PackagedCrate {
+    pub file_name: String,
+    pub bytes: Vec<u8>,
+    /// Hex encoded SHA-256 of `bytes`.
+    pub sha256: String,
+}
+
+impl PackagedCrate {
+    /// Finds the `.crate` in an archive of cargo's `package` directory. Publishing more
+    /// than one crate at a time isn't supported.
+    pub fn from_package_archive(archive: &[u8]) -> eyre::Result<PackagedCrate> {
+        let mut crates = Vec::new();
+        for entry in tar::Archive::new(archive).entries()? {
+            let mut entry = entry?;
+            let path = entry.path()?.into_owned();
+            let is_crate = path.extension().is_some_and(|extension| extension == "crate");
+            if !is_crate || !entry.header().entry_type().is_file() {
+                continue;
+            }
+            let mut bytes = Vec::new();
+            entry.read_to_end(&mut bytes)?;
+            let file_name = path.file_name().unwrap_or_default().to_string_lossy().into_owned();
+            crates.push((file_name, bytes));
+        }
+
+        let mut crates = crates.into_iter();
+        let (Some((file_name, bytes)), None) = (crates.next(), crates.next()) else {
+            eyre::bail!("Expected `cargo package` to produce exactly one .crate");
+        };
+        let sha256 = Sha256::digest(&bytes).iter().map(|byte| format!("{byte:02x}")).collect();
+        Ok(PackagedCrate { file_name, bytes, sha256 })
+    }
+
+    /// Reads a file from the `.crate`, by its path relative to the package root.
+    fn read_file(&self, relative_path: &str) -> eyre::Result<Option<String>> {
+        let root = self.file_name.trim_end_matches(".crate");
+        let mut archive = tar::Archive::new(GzDecoder::new(self.bytes.as_slice()));
+        for entry in archive.entries()? {
+            let mut entry = entry?;
+            if entry.path()? == Path::new(root).join(relative_path) {
+                let mut contents = String::new();
+                entry.read_to_string(&mut contents)?;
+                return Ok(Some(contents));
+            }
+        }
+        Ok(None)
+    }
+
+    /// The metadata that the registry's publish endpoint expects alongside the `.crate`,
+    /// taken from the normalized `Cargo.toml` inside it, as cargo does.
+    fn publish_metadata(&self, registry: Option<&Registry>) -> eyre::Result<serde_json::Value> {
+        let Some(manifest) = self.read_file("Cargo.toml")? else {
+            eyre::bail!("{} does not contain a Cargo.toml", self.file_name);
+        };
+        let manifest: toml::Value = toml::from_str(&manifest)
+            .wrap_err_with(|| format!("Invalid Cargo.toml in {}", self.file_name))?;
+        let Some(package) = manifest.get("package") else {
+            eyre::bail!("The Cargo.toml in {} has no [package]", self.file_name);
+        };
+        let string = |key: &str| package.get(key).and_then(toml::Value::as_str).map(str::to_string);
+        let strings = |key: &str| -> Vec<String> {
+            let values = package.get(key).and_then(toml::Value::as_array);
+            values.into_iter().flatten().filter_map(toml::Value::as_str).map(str::to_string).collect()
+        };
+
+        let publish_index = registry.map_or(CRATES_IO_INDEX, |registry| registry.index.as_str());
+        let mut deps = Vec::new();
+        collect_dependencies(&manifest, None, publish_index, &mut deps);
+        if let Some(targets) = manifest.get("target").and_then(toml::Value::as_table) {
+            for (target, tables) in targets {
+                collect_dependencies(tables, Some(target), publish_index, &mut deps);
+            }
+        }
+
+        let readme_file = string("readme");
+        let readme = match &readme_file {
+            Some(readme_file) => self.read_file(readme_file)?,
+            None => None,
+        };
+
+        Ok(json!({
+            "name": string("name"),
+            "vers": string("version"),
+            "deps": deps,
+            "features": manifest.get("features").cloned().unwrap_or_else(|| toml::Value::Table(Default::default())),
+            "authors": strings("authors"),
+            "description": string("description"),
+            "documentation": string("documentation"),
+            "homepage": string("homepage"),
+            "readme": readme,
+            "readme_file": readme_file,
+            "keywords": strings("keywords"),
+            "categories": strings("categories"),
+            "license": string("license"),
+            "license_file": string("license-file"),
+            "repository": string("repository"),
+            "badges": {},
+            "links": string("links"),
+            "rust_version": string("rust-version"),
+        }))
+    }
+
+    /// A tar archive to extract into the Publish container, holding the `.crate` and the
+    /// part of the upload that precedes it: the length of the metadata, the metadata, and
+    /// the length of the `.crate`.
+    pub fn upload_archive(&self, registry: Option<&Registry>) -> eyre::Result<Vec<u8>> {
+        let metadata = serde_json::to_vec(&self.publish_metadata(registry)?)?;
+        let mut request_head = Vec::with_capacity(metadata.len() + 8);
+        request_head.extend_from_slice(&u32::try_from(metadata.len())?.to_le_bytes());
+        request_head.extend_from_slice(&metadata);
+        request_head.extend_from_slice(&u32::try_from(self.bytes.len())?.to_le_bytes());
+
+        let mut archive = tar::Builder::new(Vec::new());
+        for (name, contents) in [("request-head", request_head.as_slice()), (self.file_name.as_str(), &self.bytes)] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(contents.len() as u64);
+            header.set_mode(0o644);
+            archive.append_data(&mut header, name, contents)?;
+        }
+        Ok(archive.into_inner()?)
+    }
+
+    /// The command that checks and uploads the `.crate` in the Publish container.
+    pub fn upload_command(&self, registry: Option<&Registry>) -> Vec<String> {
+        let script = format!(
+            "UPLOAD_DIR={DOCKER_UPLOAD_DIR}\nCARGO_HOME={DOCKER_CARGO_HOME}\nCRATES_IO_API={CRATES_IO_API}\n{UPLOAD_SCRIPT}"
+        );
+        vec![
+            "bash".to_string(),
+            "-c".to_string(),
+            script,
+            "upload".to_string(),
+            self.sha256.clone(),
+            self.file_name.clone(),
+            registry.map(|registry| registry.index.clone()).unwrap_or_default(),
+        ]
+    }
+}
+
+/// Adds the dependencies in the dependency tables of `tables`, which is either the
+/// manifest or one of its `target.<cfg>` tables. Like cargo, only dependencies from another
+/// registry than `publish_index` name their registry.
+fn collect_dependencies(
+    tables: &toml::Value,
+    target: Option<&str>,
+    publish_index: &str,
+    deps: &mut Vec<serde_json::Value>,
+) {
+    for (table, kind) in [("dependencies", "normal"), ("dev-dependencies", "dev"), ("build-dependencies", "build")] {
+        let Some(dependencies) = tables.get(table).and_then(toml::Value::as_table) else {
+            continue;
+        };
+        for (name_in_toml, dependency) in dependencies {
+            // A bare string is just the version requirement
+            let field = |key: &str| dependency.get(key);
+            let version_req = dependency
+                .as_str()
+                .or_else(|| field("version").and_then(toml::Value::as_str))
+                .unwrap_or("*");
+            let package = field("package").and_then(toml::Value::as_str);
+            let features: Vec<&str> = field("features")
+                .and_then(toml::Value::as_array)
+                .into_iter()
+                .flatten()
+                .filter_map(toml::Value::as_str)
+                .collect();
+            let index = field("registry-index").and_then(toml::Value::as_str).unwrap_or(CRATES_IO_INDEX);
+            let registry = (index.trim_end_matches('/') != publish_index.trim_end_matches('/')).then_some(index);
+            deps.push(json!({
+                "name": package.unwrap_or(name_in_toml),
+                "version_req": version_req,
+                "features": features,
+                "optional": field("optional").and_then(toml::Value::as_bool).unwrap_or(false),
+                "default_features": field("default-features").and_then(toml::Value::as_bool).unwrap_or(true),
+                "target": target,
+                "kind": kind,
+                "registry": registry,
+                "explicit_name_in_toml": package.map(|_| name_in_toml),
+            }));
+        }
+    }
+}
+
+/// An alternative registry, as configured in cargo's configuration on the host.
+pub struct Registry {
+    pub name: String,
+    /// The URL of the registry's index, with a `sparse+` prefix for sparse indexes.
+    pub index: String,
+}
+
+impl Registry {
+    /// Looks up the index of `name` the way cargo would: the environment, then
+    /// `.cargo/config.toml` from the current directory upwards, then `$CARGO_HOME/config.toml`.
+    /// Neither container can see cargo's configuration on the host.
+    pub fn locate(project: &Project, name: &str) -> eyre::Result<Registry> {
+        let registry = |index: &str| Registry {
+            name: name.to_string(),
+            index: index.to_string(),
+        };
+        let env_name = index_env_name(name);
+        if let Some(index) = std::env::var_os(&env_name) {
+            return Ok(registry(&index.to_string_lossy()));
+        }
+
+        let mut config_dirs: Vec<PathBuf> = project
+            .root
+            .join(&project.relative_dir)
+            .ancestors()
+            .map(|dir| dir.join(".cargo"))
+            .collect();
+        let cargo_home = std::env::var_os("CARGO_HOME")
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cargo")));
+        config_dirs.extend(cargo_home);
+
+        for dir in config_dirs {
+            for file_name in ["config.toml", "config"] {
+                let config_path = dir.join(file_name);
+                let Some(contents) = read_optional(&config_path)? else {
+                    continue;
+                };
+                let config: toml::Value = toml::from_str(&contents)
+                    .map_err(|e| eyre::eyre!("Invalid cargo configuration {}\n{e}", config_path.display()))?;
+                let index = config
+                    .get("registries")
+                    .and_then(|registries| registries.get(name))
+                    .and_then(|registry| registry.get("index"))
+                    .and_then(toml::Value::as_str);
+                if let Some(index) = index {
+                    return Ok(registry(index));
+                }
+                break;
+            }
+        }
+        eyre::bail!("The registry `{name}` isn't configured in any .cargo/config.toml, or in {env_name}")
+    }
+
+    /// The environment variable that tells cargo in the Build container where the index is.
+    pub fn index_env(&self) -> String {
+        format!("{}={}", index_env_name(&self.name), self.index)
+    }
+}
+
+fn index_env_name(registry: &str) -> String {
+    format!("CARGO_REGISTRIES_{}_INDEX", registry.to_ascii_uppercase().replace('-', "_"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const INTERNAL_INDEX: &str = "sparse+https://registry.example.com/index/";
+
+    fn registries(publish_index: &str) -> Vec<serde_json::Value> {
+        let manifest: toml::Value = toml::from_str(&format!(
+            r#"
+            [dependencies]
+            serde = "1.0"
+            internal = {{ version = "0.3", registry-index = "{INTERNAL_INDEX}" }}
+            "#
+        ))
+        .unwrap();
+        let mut deps = Vec::new();
+        collect_dependencies(&manifest, None, publish_index, &mut deps);
+        deps.into_iter().map(|dep| dep["registry"].clone()).collect()
+    }
+
+    #[test]
+    fn only_dependencies_from_other_registries_name_theirs() {
+        // Dependencies are in alphabetical order
+        assert_eq!(registries(CRATES_IO_INDEX), vec![json!(INTERNAL_INDEX), json!(null)]);
+        assert_eq!(registries(INTERNAL_INDEX), vec![json!(null), json!(CRATES_IO_INDEX)]);
+    }
+}
@@ -0,0 +1,182 @@
+use std::str::FromStr;
+
+use crate::config::LimitsConfig;
+use crate::dockerapi::port::Type;
+
+/// Flags that configure `cargo-sandbox` itself rather than the cargo command being run.
+///
+/// These are removed from the arguments before they are passed on to cargo. Only the
+/// arguments before a `--` are considered, everything after it belongs to cargo (or to
+/// libtest, in the case of `cargo test`).
+#[derive(Clone, Debug, Default)]
+pub struct SandboxArgs {
+    /// `--allow-network`: give commands that run without networking by default, such as
+    /// `test`, access to the network.
+    pub allow_network: bool,
+    /// `--port <[host_ip:][host_port:]container_port[/protocol]>`: publish a container port
+    /// to the host when using `run`. May be given more than once.
+    pub ports: Vec<PublishedPort>,
+    /// `--docker-host <unix:///path/to/socket>`: the docker daemon to use, overriding
+    /// `DOCKER_HOST` and the active docker context.
+    pub docker_host: Option<String>,
+    /// `--memory <size>`, `--memory-swap <size>`, `--cpus <n>`, `--pids-limit <n>`,
+    /// `--storage-size <size>`, `--ulimit <name>=<limit>` and `--timeout <duration>`: resource
+    /// limits that take precedence over the `[limits]` configuration.
+    pub limits: LimitsConfig,
+}
+
+impl SandboxArgs {
+    pub fn extract(args: &mut Vec<String>) -> eyre::Result<SandboxArgs> {
+        let mut sandbox_args = SandboxArgs::default();
+
+        let mut index = 0;
+        while index < args.len() {
+            match args[index].as_str() {
+                "--" => break,
+                "--allow-network" => {
+                    args.remove(index);
+                    sandbox_args.allow_network = true;
+                }
+                arg if is_flag(arg, "--port") => {
+                    let value = take_value(args, index, "--port")?;
+                    sandbox_args.ports.push(value.parse()?);
+                }
+                arg if is_flag(arg, "--docker-host") => {
+                    sandbox_args.docker_host = Some(take_value(args, index, "--docker-host")?);
+                }
+                arg if is_flag(arg, "--memory") => {
+                    sandbox_args.limits.memory = Some(take_value(args, index, "--memory")?.parse()?);
+                }
+                arg if is_flag(arg, "--memory-swap") => {
+                    sandbox_args.limits.memory_swap = Some(take_value(args, index, "--memory-swap")?.parse()?);
+                }
+                arg if is_flag(arg, "--cpus") => {
+                    sandbox_args.limits.cpus = Some(take_value(args, index, "--cpus")?.parse()?);
+                }
+                arg if is_flag(arg, "--pids-limit") => {
+                    sandbox_args.limits.pids = Some(take_value(args, index, "--pids-limit")?.parse()?);
+                }
+                arg if is_flag(arg, "--storage-size") => {
+                    sandbox_args.limits.storage = Some(take_value(args, index, "--storage-size")?.parse()?);
+                }
+                arg if is_flag(arg, "--timeout") => {
+                    sandbox_args.limits.timeout = Some(take_value(args, index, "--timeout")?.parse()?);
+                }
+                arg if is_flag(arg, "--ulimit") => {
+                    let value = take_value(args, index, "--ulimit")?;
+                    let limit = value.split_once('=').and_then(|(name, limit)| Some((name, limit.parse().ok()?)));
+                    let Some((name, limit)) = limit else {
+                        eyre::bail!("Invalid --ulimit `{value}`, expected e.g. `nofile=4096`");
+                    };
+                    sandbox_args.limits.ulimits.insert(name.to_string(), limit);
+                }
+                _ => index += 1,
+            }
+        }
+
+        Ok(sandbox_args)
+    }
+}
+
+fn is_flag(arg: &str, flag: &str) -> bool {
+    arg == flag || arg.strip_prefix(flag).is_some_and(|rest| rest.starts_with('='))
+}
+
+/// Removes a `--flag value` or `--flag=value` pair starting at `index` and returns the value.
+fn take_value(args: &mut Vec<String>, index: usize, flag: &str) -> eyre::Result<String> {
+    let arg = args.remove(index);
+    if let Some((_, value)) = arg.split_once('=') {
+        return Ok(value.to_string());
+    }
+    if index < args.len() && args[index] != "--" {
+        return Ok(args.remove(index));
+    }
+    eyre::bail!("{flag} requires a value")
+}
+
+/// A container port that is published to the host, in the same format that
+/// `docker run --publish` uses. Unless a host IP is given, ports are only
+/// published on the loopback interface.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PublishedPort {
+    pub host_ip: String,
+    /// `None` if only the container port was given, in which case the daemon picks a free
+    /// host port, as `docker run --publish` does.
+    pub host_port: Option<u16>,
+    pub container_port: u16,
+    pub protocol: Type,
+}
+
+impl PublishedPort {
+    /// The key used for this port in `ExposedPorts` and `PortBindings`, e.g. `80/tcp`.
+    pub fn container_key(&self) -> String {
+        format!("{}/{}", self.container_port, self.protocol.as_str())
+    }
+}
+
+impl FromStr for PublishedPort {
+    type Err = eyre::Report;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (ports, protocol) = match s.split_once('/') {
+            Some((ports, "tcp")) => (ports, Type::Tcp),
+            Some((ports, "udp")) => (ports, Type::Udp),
+            Some((ports, "sctp")) => (ports, Type::Sctp),
+            Some((_, other)) => eyre::bail!("Invalid port protocol `{other}` in `{s}`"),
+            None => (s, Type::Tcp),
+        };
+
+        let parse_port = |port: &str| {
+            port.parse::<u16>()
+                .map_err(|e| eyre::eyre!("Invalid port `{port}` in `{s}`: {e}"))
+        };
+
+        let mut parts = ports.rsplitn(3, ':');
+        let container_port = parse_port(parts.next().unwrap_or_default())?;
+        let host_port = match parts.next() {
+            Some("") | None => None,
+            Some(host_port) => Some(parse_port(host_port)?),
+        };
+        let host_ip = parts.next().unwrap_or("127.0.0.1").to_string();
+
+        Ok(PublishedPort {
+            host_ip,
+            host_port,
+            container_port,
+            protocol,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn container_port_alone_gets_an_ephemeral_host_port() {
+        let port: PublishedPort = "80".parse().unwrap();
+        assert_eq!(port.host_port, None);
+        assert_eq!(port.host_ip, "127.0.0.1");
+
+        let port: PublishedPort = "0.0.0.0::80/udp".parse().unwrap();
+        assert_eq!(port.host_port, None);
+        assert_eq!(port.container_key(), "80/udp");
+
+        let port: PublishedPort = "8080:80".parse().unwrap();
+        assert_eq!(port.host_port, Some(8080));
+    }
+
+    #[test]
+    fn limit_flags_are_validated() {
+        let mut args: Vec<String> = ["build", "--cpus=1.5", "--pids-limit", "512", "--release"].map(String::from).to_vec();
+        let sandbox_args = SandboxArgs::extract(&mut args).unwrap();
+        assert_eq!(args, vec!["build".to_string(), "--release".to_string()]);
+        assert_eq!(sandbox_args.limits.cpus.map(|cpus| cpus.0), Some(1.5));
+        assert_eq!(sandbox_args.limits.pids.map(|pids| pids.0), Some(512));
+
+        for invalid in [["--cpus", "-1"], ["--cpus", "NaN"], ["--pids-limit", "0"]] {
+            let mut args: Vec<String> = invalid.map(String::from).to_vec();
+            assert!(SandboxArgs::extract(&mut args).is_err(), "{invalid:?} is rejected");
+        }
+    }
+}
@@ -0,0 +1,63 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use tokio::signal::unix::{signal, Signal, SignalKind};
+
+/// Whether a running container currently receives our SIGINT and SIGTERM.
+static FORWARDING: AtomicBool = AtomicBool::new(false);
+
+/// Once tokio handles a signal it does so for the rest of the process, so without this
+/// Ctrl-C would be ignored whenever no container is running, e.g. while an image builds or
+/// between the fetch and the build container. Unless they are being forwarded, SIGINT and
+/// SIGTERM end cargo-sandbox as they would have by default.
+pub fn exit_unless_forwarded() -> eyre::Result<()> {
+    let mut interrupt = signal(SignalKind::interrupt())?;
+    let mut terminate = signal(SignalKind::terminate())?;
+    tokio::spawn(async move {
+        loop {
+            let signal_number = tokio::select! {
+                _ = interrupt.recv() => libc::SIGINT,
+                _ = terminate.recv() => libc::SIGTERM,
+            };
+            if !FORWARDING.load(Ordering::SeqCst) {
+                std::process::exit(128 + signal_number);
+            }
+        }
+    });
+    Ok(())
+}
+
+/// SIGINT and SIGTERM that arrive while a container runs, to forward to it. Only signals
+/// received after this is created are seen, and until it is dropped they don't end cargo-sandbox.
+pub struct Forwarding {
+    interrupt: Signal,
+    terminate: Signal,
+}
+
+impl Forwarding {
+    pub fn start() -> eyre::Result<Forwarding> {
+        // Set first, a signal in between is better lost than ending us with the container running
+        FORWARDING.store(true, Ordering::SeqCst);
+        let signals = signal(SignalKind::interrupt()).and_then(|interrupt| Ok((interrupt, signal(SignalKind::terminate())?)));
+        match signals {
+            Ok((interrupt, terminate)) => Ok(Forwarding { interrupt, terminate }),
+            Err(e) => {
+                FORWARDING.store(false, Ordering::SeqCst);
+                Err(e.into())
+            }
+        }
+    }
+
+    /// Waits for the next signal and returns its name.
+    pub async fn recv(&mut self) -> &'static str {
+        tokio::select! {
+            _ = self.interrupt.recv() => "SIGINT",
+            _ = self.terminate.recv() => "SIGTERM",
+        }
+    }
+}
+
+impl Drop for Forwarding {
+    fn drop(&mut self) {
+        FORWARDING.store(false, Ordering::SeqCst);
+    }
+}
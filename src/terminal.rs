@@ -0,0 +1,83 @@
+use std::io::IsTerminal;
+use std::os::unix::io::AsRawFd;
+
+/// Whether output goes to a terminal, in which case the container gets a TTY so that
+/// cargo shows colors and progress bars.
+pub fn stdout_is_terminal() -> bool {
+    std::io::stdout().is_terminal()
+}
+
+pub fn stdin_is_terminal() -> bool {
+    std::io::stdin().is_terminal()
+}
+
+/// The size of the terminal as `(rows, columns)`, if stdout is a terminal.
+pub fn window_size() -> Option<(u16, u16)> {
+    let mut size: libc::winsize = unsafe { std::mem::zeroed() };
+    // SAFETY: TIOCGWINSZ only writes a `winsize` through the pointer
+    let result = unsafe { libc::ioctl(std::io::stdout().as_raw_fd(), libc::TIOCGWINSZ, &mut size) };
+    if result != 0 || size.ws_row == 0 || size.ws_col == 0 {
+        return None;
+    }
+    Some((size.ws_row, size.ws_col))
+}
+
+/// Puts the terminal on stdin into raw mode, so that every keypress, including Ctrl-C,
+/// goes to the container's TTY. The previous mode is restored when this is dropped.
+pub struct RawModeGuard {
+    original: libc::termios,
+}
+
+impl RawModeGuard {
+    pub fn enable() -> eyre::Result<RawModeGuard> {
+        let fd = std::io::stdin().as_raw_fd();
+        let mut original: libc::termios = unsafe { std::mem::zeroed() };
+        // SAFETY: tcgetattr only writes a `termios` through the pointer
+        if unsafe { libc::tcgetattr(fd, &mut original) } != 0 {
+            return Err(std::io::Error::last_os_error().into());
+        }
+
+        let mut raw = original;
+        // SAFETY: cfmakeraw only modifies the `termios` it is given
+        unsafe { libc::cfmakeraw(&mut raw) };
+        // SAFETY: tcsetattr only reads the `termios` through the pointer
+        if unsafe { libc::tcsetattr(fd, libc::TCSANOW, &raw) } != 0 {
+            return Err(std::io::Error::last_os_error().into());
+        }
+
+        Ok(RawModeGuard { original })
+    }
+}
+
+impl Drop for RawModeGuard {
+    fn drop(&mut self) {
+        let fd = std::io::stdin().as_raw_fd();
+        // SAFETY: tcsetattr only reads the `termios` through the pointer
+        unsafe { libc::tcsetattr(fd, libc::TCSANOW, &self.original) };
+    }
+}
+
+/// Reads a line from stdin, without echoing it if stdin is a terminal.
+pub fn read_secret_line() -> eyre::Result<String> {
+    let fd = std::io::stdin().as_raw_fd();
+    let mut original: libc::termios = unsafe { std::mem::zeroed() };
+    // SAFETY: tcgetattr only writes a `termios` through the pointer
+    let echo_disabled = stdin_is_terminal() && unsafe { libc::tcgetattr(fd, &mut original) } == 0 && {
+        let mut silent = original;
+        silent.c_lflag &= !libc::ECHO;
+        // SAFETY: tcsetattr only reads the `termios` through the pointer
+        unsafe { libc::tcsetattr(fd, libc::TCSANOW, &silent) == 0 }
+    };
+
+    let mut line = String::new();
+    let result = std::io::stdin().read_line(&mut line);
+
+    if echo_disabled {
+        // SAFETY: tcsetattr only reads the `termios` through the pointer
+        unsafe { libc::tcsetattr(fd, libc::TCSANOW, &original) };
+        // The newline wasn't echoed either
+        eprintln!();
+    }
+    result?;
+    Ok(line.trim().to_string())
+}
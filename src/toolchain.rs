@@ -0,0 +1,337 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use maplit::hashmap;
+use serde::Deserialize;
+
+use crate::config::read_optional;
+use crate::container_type::ContainerType;
+use crate::dockerapi::client::Client;
+use crate::dockerapi::create_container_args::{CreateContainerArgs, HostConfig};
+use crate::dockerapi::create_volume_args::CreateVolumeArgs;
+use crate::dockerapi::list_volumes::ListVolumesArgs;
+use crate::dockerapi::mount::Mount;
+use crate::dockerapi::volume::Volume;
+use crate::project::Project;
+
+/// `RUSTUP_HOME` in the Build container when a toolchain volume is mounted. The directory
+/// exists in the image, so the volume inherits its ownership.
+pub const DOCKER_RUSTUP_HOME: &str = "/home/cargo-sandbox-user/.rustup";
+
+/// The toolchain a project asks for, from `rust-toolchain.toml`, `rust-toolchain`, or
+/// failing that the `rust-version` in `Cargo.toml`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Toolchain {
+    pub channel: String,
+    pub components: Vec<String>,
+    pub targets: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct ToolchainFile {
+    toolchain: ToolchainSection,
+}
+
+#[derive(Deserialize)]
+struct ToolchainSection {
+    channel: Option<String>,
+    path: Option<String>,
+    #[serde(default)]
+    components: Vec<String>,
+    #[serde(default)]
+    targets: Vec<String>,
+}
+
+impl Toolchain {
+    pub fn detect(project: &Project) -> eyre::Result<Option<Toolchain>> {
+        for file_name in ["rust-toolchain.toml", "rust-toolchain"] {
+            let path = project.root.join(file_name);
+            if let Some(contents) = read_optional(&path)? {
+                return Toolchain::parse_toolchain_file(&path, &contents).map(Some);
+            }
+        }
+
+        let Some(root_manifest) = read_optional(&project.root.join("Cargo.toml"))? else {
+            return Ok(None);
+        };
+        // The package in the current directory, which may inherit from the root's `[workspace.package]`
+        let current_dir = project.root.join(&project.relative_dir);
+        let mut package_manifest = None;
+        for dir in current_dir.ancestors().take_while(|dir| *dir != project.root) {
+            if let Some(contents) = read_optional(&dir.join("Cargo.toml"))? {
+                package_manifest = Some(contents);
+                break;
+            }
+        }
+        let package_manifest = package_manifest.as_deref().unwrap_or(&root_manifest);
+
+        Ok(rust_version(package_manifest, &root_manifest).map(|channel| Toolchain {
+            channel,
+            ..Default::default()
+        }))
+    }
+
+    /// `rust-toolchain` may also hold just the name of the channel.
+    fn parse_toolchain_file(path: &Path, contents: &str) -> eyre::Result<Toolchain> {
+        let file: ToolchainFile = match toml::from_str(contents) {
+            Ok(file) => file,
+            Err(_) if !contents.trim().is_empty() && !contents.trim().contains(char::is_whitespace) => {
+                return Ok(Toolchain {
+                    channel: contents.trim().to_string(),
+                    ..Default::default()
+                });
+            }
+            Err(e) => eyre::bail!("Invalid toolchain file {}\n{e}", path.display()),
+        };
+
+        let section = file.toolchain;
+        if section.path.is_some() {
+            eyre::bail!(
+                "{} uses a custom toolchain `path`, which doesn't exist in the sandbox",
+                path.display()
+            );
+        }
+        let Some(channel) = section.channel else {
+            eyre::bail!("{} does not set a toolchain channel", path.display());
+        };
+        Ok(Toolchain {
+            channel,
+            components: section.components,
+            targets: section.targets,
+        })
+    }
+
+    /// Full versions like `1.70.0` have a `rust` image of their own, so no toolchain has to
+    /// be installed as long as no extra components or targets are needed. `1.70` has an
+    /// image too, but rustup treats it as a different toolchain than the `1.70.0` inside it.
+    pub fn rust_image_version(&self) -> Option<&str> {
+        let parts: Vec<&str> = self.channel.split('.').collect();
+        let is_version = parts.len() == 3
+            && parts.iter().all(|part| !part.is_empty() && part.bytes().all(|b| b.is_ascii_digit()));
+        (is_version && self.components.is_empty() && self.targets.is_empty()).then_some(self.channel.as_str())
+    }
+
+    /// The name of the volume that points at where this toolchain is installed. Projects
+    /// that ask for the same toolchain share the installation, which is why it is only ever
+    /// mounted read-only while project code runs.
+    fn volume_name(&self) -> String {
+        let mut name = format!("cargo-sandbox-toolchain-{}", self.channel);
+        for extra in self.components.iter().chain(&self.targets) {
+            name.push('-');
+            name.push_str(extra);
+        }
+        name.chars()
+            .map(|c| if c.is_ascii_alphanumeric() || c == '.' || c == '-' || c == '_' { c } else { '_' })
+            .collect()
+    }
+
+    fn install_command(&self) -> Vec<String> {
+        let mut command = vec![
+            "rustup".to_string(),
+            "toolchain".to_string(),
+            "install".to_string(),
+            self.channel.clone(),
+            "--profile".to_string(),
+            "minimal".to_string(),
+        ];
+        for component in &self.components {
+            command.extend(["--component".to_string(), component.clone()]);
+        }
+        for target in &self.targets {
+            command.extend(["--target".to_string(), target.clone()]);
+        }
+        command
+    }
+}
+
+/// `package.rust-version` of `package_manifest`, or `workspace.package.rust-version` of the
+/// workspace root's manifest if the package inherits it or there is no package, as in a
+/// virtual workspace.
+fn rust_version(package_manifest: &str, root_manifest: &str) -> Option<String> {
+    let package_manifest: toml::Value = toml::from_str(package_manifest).ok()?;
+    if let Some(package) = package_manifest.get("package") {
+        let from_package = package.get("rust-version")?;
+        if let Some(version) = from_package.as_str() {
+            return Some(version.to_string());
+        }
+        // `rust-version.workspace = true`
+        if from_package.get("workspace").and_then(toml::Value::as_bool) != Some(true) {
+            return None;
+        }
+    }
+
+    let root_manifest: toml::Value = toml::from_str(root_manifest).ok()?;
+    let version = root_manifest.get("workspace")?.get("package")?.get("rust-version")?.as_str()?;
+    Some(version.to_string())
+}
+
+/// Labels the volume named after a toolchain with the volume the toolchain is installed in.
+const INSTALLED_LABEL: &str = "cargo-sandbox.toolchain-volume";
+
+/// Returns the mount of the volume holding `toolchain`, installing it first if it isn't
+/// installed yet. Installing needs the network, but doesn't run any project code.
+///
+/// Each installation goes into a volume of its own. Only once it is complete is a volume
+/// named after the toolchain created to point at it, so nothing ever mounts a toolchain that
+/// is still being installed. When two invocations install the same toolchain at once, the
+/// first to finish wins and the other removes its copy.
+pub async fn toolchain_mount(client: &Client, image: &str, toolchain: &Toolchain) -> eyre::Result<Mount> {
+    let name = toolchain.volume_name();
+    let existing = client
+        .list_volumes(ListVolumesArgs {
+            filters: Some(serde_json::json!({ "name": [&name] }).to_string()),
+        })
+        .await?;
+    // The name filter matches substrings
+    let installed = match existing.into_iter().find(|volume| volume.name == name) {
+        Some(marker) => installed_volume(&marker)?,
+        None => {
+            let volume = format!("{name}-{}", std::process::id());
+            install(client, image, toolchain, &volume).await?;
+            let marker = client
+                .create_volume(CreateVolumeArgs {
+                    name: name.clone(),
+                    labels: toolchain_labels(Some(&volume)),
+                    ..Default::default()
+                })
+                .await?;
+            // Docker returns the existing volume if another invocation finished first
+            let installed = installed_volume(&marker)?;
+            if installed != volume {
+                if let Err(e) = client.remove_volume(&volume, true).await {
+                    eprintln!("Failed to remove toolchain volume {volume}: {e}");
+                }
+            }
+            installed
+        }
+    };
+
+    Ok(Mount {
+        read_only: true,
+        ..Mount::volume(&installed, DOCKER_RUSTUP_HOME)
+    })
+}
+
+/// The volume that the volume named after a toolchain points at.
+fn installed_volume(marker: &Volume) -> eyre::Result<String> {
+    let installed = marker.labels.as_ref().and_then(|labels| labels.get(INSTALLED_LABEL));
+    installed.cloned().ok_or_else(|| {
+        eyre::eyre!(
+            "The toolchain volume {} doesn't say where the toolchain is installed, remove it with `cargo-sandbox cache prune --all`",
+            marker.name
+        )
+    })
+}
+
+/// Toolchain volumes are labelled as a cache, so that `cache prune --all` removes them.
+fn toolchain_labels(installed: Option<&str>) -> HashMap<String, String> {
+    let mut labels = hashmap! {
+        "cargo-sandbox.version".into() => env!("CARGO_PKG_VERSION").to_string(),
+        "cargo-sandbox.cache".into() => "toolchain".into(),
+    };
+    if let Some(installed) = installed {
+        labels.insert(INSTALLED_LABEL.into(), installed.to_string());
+    }
+    labels
+}
+
+async fn install(client: &Client, image: &str, toolchain: &Toolchain, volume: &str) -> eyre::Result<()> {
+    eprintln!("Installing the {} toolchain", toolchain.channel);
+    client
+        .create_volume(CreateVolumeArgs {
+            name: volume.to_string(),
+            labels: toolchain_labels(None),
+            ..Default::default()
+        })
+        .await?;
+
+    let result = async {
+        let container = client
+            .create_container(CreateContainerArgs {
+                cmd: toolchain.install_command(),
+                image: image.to_string(),
+                user: crate::DOCKER_USER.into(),
+                env: vec![format!("RUSTUP_HOME={DOCKER_RUSTUP_HOME}")],
+                attach_stdout: true,
+                attach_stderr: true,
+                host_config: HostConfig {
+                    security_opt: ContainerType::Build.security_opts(true),
+                    cap_drop: vec!["ALL".to_string()],
+                    readonly_rootfs: true,
+                    mounts: vec![Mount::volume(volume, DOCKER_RUSTUP_HOME), Mount::tmpfs("/tmp")],
+                    ..Default::default()
+                },
+                ..Default::default()
+            })
+            .await?;
+
+        let attached = client.attach(&container.id).await?;
+        let output = tokio::spawn(attached.forward(false, false));
+        client.start_container(&container.id).await?;
+        output.await??;
+        let exit = client.wait(container.id.clone()).await?;
+        client.remove_container(container.id, true, true).await?;
+
+        if exit.status_code != 0 {
+            eyre::bail!("Installing the {} toolchain failed", toolchain.channel);
+        }
+        Ok(())
+    }
+    .await;
+
+    // A half-installed toolchain would otherwise be left behind until the next prune
+    if result.is_err() {
+        if let Err(e) = client.remove_volume(volume, true).await {
+            eprintln!("Failed to remove toolchain volume {volume}: {e}");
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WORKSPACE_ROOT: &str = r#"
+        [workspace]
+        members = ["member"]
+
+        [workspace.package]
+        rust-version = "1.70.0"
+    "#;
+
+    #[test]
+    fn only_full_versions_use_a_rust_image() {
+        let toolchain = |channel: &str| Toolchain {
+            channel: channel.to_string(),
+            ..Default::default()
+        };
+        assert_eq!(toolchain("1.70.0").rust_image_version(), Some("1.70.0"));
+        assert_eq!(toolchain("1.70").rust_image_version(), None);
+        assert_eq!(toolchain("stable").rust_image_version(), None);
+    }
+
+    #[test]
+    fn member_inherits_rust_version_from_the_workspace_root() {
+        let member = r#"
+            [package]
+            name = "member"
+            rust-version.workspace = true
+        "#;
+        assert_eq!(rust_version(member, WORKSPACE_ROOT).as_deref(), Some("1.70.0"));
+    }
+
+    #[test]
+    fn virtual_workspace_root_uses_workspace_package() {
+        assert_eq!(rust_version(WORKSPACE_ROOT, WORKSPACE_ROOT).as_deref(), Some("1.70.0"));
+    }
+
+    #[test]
+    fn package_without_rust_version_does_not_inherit() {
+        let member = r#"
+            [package]
+            name = "member"
+        "#;
+        assert_eq!(rust_version(member, WORKSPACE_ROOT), None);
+    }
+}